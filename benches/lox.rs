@@ -0,0 +1,68 @@
+//! Time the `.lox` programs in this directory on each backend.
+//!
+//! `cargo bench` runs every program several times and reports the median and fastest
+//! wall-clock time of the `warlox` binary; `cargo bench -- NAME` only runs programs whose
+//! file name contains NAME. `cargo test --benches` runs each program once as a check.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+const BACKENDS: [&str; 2] = ["tree", "vm"];
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let bench = args.iter().any(|v| v == "--bench");
+    let filter = args.iter().find(|v| !v.starts_with("--"));
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches");
+    let mut programs: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("benches directory")
+        .filter_map(|entry| entry.ok().map(|v| v.path()))
+        .filter(|path| path.extension().is_some_and(|v| v == "lox"))
+        .filter(|path| match filter {
+            Some(v) => path.to_string_lossy().contains(v.as_str()),
+            None => true,
+        })
+        .collect();
+    programs.sort();
+
+    for program in &programs {
+        let name = program.file_stem().unwrap().to_string_lossy();
+        for backend in BACKENDS {
+            if !bench {
+                run(program, backend);
+                println!("{name} ({backend}) ... ok");
+                continue;
+            }
+            let mut times: Vec<Duration> = (0..RUNS).map(|_| run(program, backend)).collect();
+            times.sort();
+            println!(
+                "{name:<16} {backend:<5} median {:>10.3?}   min {:>10.3?}",
+                times[RUNS / 2],
+                times[0]
+            );
+        }
+    }
+}
+
+/// Run a program to completion, failing on any error output.
+fn run(program: &Path, backend: &str) -> Duration {
+    let start = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .arg(format!("--backend={backend}"))
+        .arg(program)
+        .output()
+        .expect("run warlox");
+    let elapsed = start.elapsed();
+    let errors = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success() && errors.is_empty(),
+        "{} ({backend}) failed:\n{errors}",
+        program.display()
+    );
+    elapsed
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::token::Value;
+use crate::typecheck::Type;
+
+/// Instructions of the virtual machine. Operands follow the opcode byte; constant
+/// indices, jump offsets, counts and local slots are big-endian `u16`, argument counts and
+/// flags `u8`, source lines `i32`. Slots number the block locals of the running chunk from
+/// its first one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Push a constant: index
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Push a copy of the top of the stack
+    Dup,
+    /// Pop a value into a new variable of the current scope: name
+    DefineVar,
+    /// Declare an uninitialized variable in the current scope: name
+    DeclareVar,
+    /// Pop a value into a new constant of the current scope: name
+    DefineConst,
+    /// Push the value of a variable: name
+    GetVar,
+    /// Push the value of a variable, or nil if it is uninitialized, for `??=`: name
+    GetVarOrNil,
+    /// Assign the top of the stack to a variable, leaving it on the stack: name
+    SetVar,
+    /// Pop a value into a block local: name, slot
+    DefineLocal,
+    /// Declare an uninitialized block local: name, slot
+    DeclareLocal,
+    /// Push the value of a block local: name, slot
+    GetLocal,
+    /// Push the value of a block local, or nil if it is uninitialized, for `??=`: name, slot
+    GetLocalOrNil,
+    /// Assign the top of the stack to a block local, leaving it on the stack: slot
+    SetLocal,
+    /// Drop the block locals from a slot on, when leaving their block: slot
+    PopLocals,
+    /// Enter a new environment for the names a block imports
+    PushScope,
+    /// Leave the innermost block environment
+    PopScope,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Typeof,
+    /// Replace the top of the stack by whether it has a type: type
+    TypeTest,
+    Print,
+    /// Jump forward: offset
+    Jump,
+    /// Jump forward if the top of the stack is falsey, leaving it there: offset
+    JumpIfFalse,
+    /// Jump forward if the top of the stack is not nil, leaving it there: offset
+    JumpIfNotNil,
+    /// Jump backward: offset
+    Loop,
+    /// Replace a module by one of its bindings: name
+    GetProperty,
+    /// Prepare `object.name(...)`: push the property of a module or error, or the string or
+    /// list receiver again, keeping the receiver below it: name
+    GetMethod,
+    /// Call a built-in method of the receiver `GetMethod` left below the arguments, or else
+    /// the property it pushed: name, line of the name, argument count
+    Invoke,
+    /// Call the value below the arguments: argument count
+    Call,
+    Index,
+    /// Slice with the bounds present on the stack: flags (1 start, 2 end)
+    Slice,
+    /// Collect values from the stack into a list: count
+    List,
+    /// Import every binding of a module into the current scope: path
+    Import,
+    /// Bind a module to a name in the current scope: path, name
+    ImportAs,
+    /// Fail an assertion, with a message on the stack if flagged: source, flag
+    AssertFail,
+    /// Raise the top of the stack as an exception
+    Throw,
+    /// Divert errors raised before the matching `PopHandler` to a handler: offset
+    PushHandler,
+    PopHandler,
+    /// Push the value of the error caught by the current handler
+    Catch,
+    /// Raise the error caught by the current handler again, e.g. after a finally block
+    Rethrow,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 54] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::Dup,
+        OpCode::DefineVar,
+        OpCode::DeclareVar,
+        OpCode::DefineConst,
+        OpCode::GetVar,
+        OpCode::GetVarOrNil,
+        OpCode::SetVar,
+        OpCode::DefineLocal,
+        OpCode::DeclareLocal,
+        OpCode::GetLocal,
+        OpCode::GetLocalOrNil,
+        OpCode::SetLocal,
+        OpCode::PopLocals,
+        OpCode::PushScope,
+        OpCode::PopScope,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Typeof,
+        OpCode::TypeTest,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::JumpIfNotNil,
+        OpCode::Loop,
+        OpCode::GetProperty,
+        OpCode::GetMethod,
+        OpCode::Invoke,
+        OpCode::Call,
+        OpCode::Index,
+        OpCode::Slice,
+        OpCode::List,
+        OpCode::Import,
+        OpCode::ImportAs,
+        OpCode::AssertFail,
+        OpCode::Throw,
+        OpCode::PushHandler,
+        OpCode::PopHandler,
+        OpCode::Catch,
+        OpCode::Rethrow,
+    ];
+
+    /// Decode an opcode byte; the table is checked against the discriminants.
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL
+            .get(byte as usize)
+            .copied()
+            .filter(|op| *op as u8 == byte)
+    }
+}
+
+/// Types usable in `is` tests, by their operand byte.
+pub const TYPES: [Type; 9] = [
+    Type::Number,
+    Type::String,
+    Type::Boolean,
+    Type::Nil,
+    Type::Error,
+    Type::Function,
+    Type::List,
+    Type::Module,
+    Type::Any,
+];
+
+/// Compiled bytecode with its constants and the source line of every byte.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub lines: Vec<i32>,
+    /// Constant index of each name, so repeated uses share one entry.
+    names: HashMap<Rc<str>, usize>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: i32) {
+        self.code.push(byte);
+        self.lines.push(line);
+    }
+
+    /// Add a constant, returning its index; strings are only stored once.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        if let Value::String(name) = &value {
+            if let Some(index) = self.names.get(name) {
+                return *index;
+            }
+            self.names.insert(name.clone(), self.constants.len());
+        }
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn read_i32(&self, offset: usize) -> i32 {
+        let bytes = self.code[offset..offset + 4]
+            .try_into()
+            .expect("truncated operand");
+        i32::from_be_bytes(bytes)
+    }
+
+    /// Name stored as a string constant.
+    pub fn name(&self, index: u16) -> &Rc<str> {
+        match &self.constants[index as usize] {
+            Value::String(name) => name,
+            _ => unreachable!(), // Compiler only refers to names by string constants.
+        }
+    }
+}
@@ -0,0 +1,746 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::chunk::{Chunk, OpCode, TYPES};
+use crate::expr::{Binding, Expr};
+use crate::stmt::{CatchClause, Stmt};
+use crate::token::{Token, TokenType, Value};
+
+#[derive(Debug)]
+pub enum CompileError {
+    /// Chunk needs more constants than an operand can address
+    TooManyConstants(i32),
+    /// Jump over more code than an operand can encode
+    JumpTooLarge(i32),
+    /// Call or list literal with more values than an operand can encode
+    TooManyValues(i32),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, line) = match self {
+            Self::TooManyConstants(line) => ("Too many constants in one chunk.", line),
+            Self::JumpTooLarge(line) => ("Too much code to jump over.", line),
+            Self::TooManyValues(line) => ("Too many arguments or list elements.", line),
+        };
+        write!(f, "{message}\n[line {line}]")
+    }
+}
+
+impl Error for CompileError {}
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// Something a `break` leaves on its way out of a loop, innermost last.
+enum Exit<'a> {
+    /// Block scope to pop, by its index in `Compiler::scopes`
+    Scope(usize),
+    /// Try handler to pop
+    Handler,
+    /// Finally block to run, with the number of scopes around its try statement
+    Finally(&'a [Stmt], usize),
+}
+
+/// A block being compiled. Its locals take the slots after those of the enclosing blocks.
+struct Scope {
+    /// Slot of the first local
+    base: usize,
+    /// Locals declared so far
+    slots: usize,
+    /// Whether the block imports names, which go in an environment of its own
+    environment: bool,
+}
+
+struct Loop {
+    /// Number of exits outside the loop
+    exits: usize,
+    /// Jumps to patch to the end of the loop
+    breaks: Vec<usize>,
+}
+
+/// Compile statements into a chunk for the virtual machine.
+pub fn compile(statements: &[Stmt]) -> Result<Chunk> {
+    let mut compiler = Compiler {
+        chunk: Chunk::new(),
+        line: 1,
+        exits: Vec::new(),
+        loops: Vec::new(),
+        scopes: Vec::new(),
+    };
+    for statement in statements {
+        compiler.statement(statement)?;
+    }
+    Ok(compiler.chunk)
+}
+
+struct Compiler<'a> {
+    chunk: Chunk,
+    /// Line of the latest token seen, given to emitted code.
+    line: i32,
+    exits: Vec<Exit<'a>>,
+    loops: Vec<Loop>,
+    scopes: Vec<Scope>,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, op: OpCode) {
+        self.chunk.write(op as u8, self.line);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.chunk.write(byte, self.line);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        for byte in value.to_be_bytes() {
+            self.emit_byte(byte);
+        }
+    }
+
+    fn emit_count(&mut self, count: usize) -> Result<()> {
+        let count = u16::try_from(count).map_err(|_| CompileError::TooManyValues(self.line))?;
+        self.emit_u16(count);
+        Ok(())
+    }
+
+    fn constant(&mut self, value: Value) -> Result<u16> {
+        let index = self.chunk.add_constant(value);
+        u16::try_from(index).map_err(|_| CompileError::TooManyConstants(self.line))
+    }
+
+    fn name(&mut self, name: &Token) -> Result<u16> {
+        self.constant(Value::String(name.lexeme.clone()))
+    }
+
+    /// Emit an instruction whose operand is the name of a token.
+    fn emit_name(&mut self, op: OpCode, name: &Token) -> Result<()> {
+        self.line = name.line;
+        let index = self.name(name)?;
+        self.emit(op);
+        self.emit_u16(index);
+        Ok(())
+    }
+
+    /// Emit an instruction declaring a named block local of the current scope.
+    fn emit_slot(&mut self, op: OpCode, name: &Token, slot: usize) -> Result<()> {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("block local outside of a block");
+        scope.slots = scope.slots.max(slot + 1);
+        let slot = scope.base + slot;
+        let name = self.name(name)?;
+        self.emit(op);
+        self.emit_u16(name);
+        self.emit_count(slot)
+    }
+
+    /// Emit an instruction on a block local `depth` scopes out, with its name for errors
+    /// if given.
+    fn emit_local(
+        &mut self,
+        op: OpCode,
+        name: Option<&Token>,
+        depth: usize,
+        slot: usize,
+    ) -> Result<()> {
+        let slot = self.scopes[self.scopes.len() - 1 - depth].base + slot;
+        let name = name.map(|name| self.name(name)).transpose()?;
+        self.emit(op);
+        if let Some(name) = name {
+            self.emit_u16(name);
+        }
+        self.emit_count(slot)
+    }
+
+    /// Push the value of a variable; with `or_nil`, nil if it is uninitialized.
+    fn variable(&mut self, token: &Token, binding: &Binding, or_nil: bool) -> Result<()> {
+        match binding {
+            Binding::Global => {
+                let op = if or_nil {
+                    OpCode::GetVarOrNil
+                } else {
+                    OpCode::GetVar
+                };
+                self.emit_name(op, token)
+            }
+            Binding::Local { depth, slot } => {
+                let op = if or_nil {
+                    OpCode::GetLocalOrNil
+                } else {
+                    OpCode::GetLocal
+                };
+                self.line = token.line;
+                self.emit_local(op, Some(token), *depth, *slot)
+            }
+        }
+    }
+
+    /// Enter a block, giving it an environment if it imports names.
+    fn begin_scope(&mut self, statements: &[Stmt]) {
+        let base = self.scopes.last().map_or(0, |v| v.base + v.slots);
+        let environment = statements.iter().any(|v| matches!(v, Stmt::Import { .. }));
+        if environment {
+            self.emit(OpCode::PushScope);
+        }
+        self.scopes.push(Scope {
+            base,
+            slots: 0,
+            environment,
+        });
+        self.exits.push(Exit::Scope(self.scopes.len() - 1));
+    }
+
+    fn end_scope(&mut self) -> Result<()> {
+        self.exits.pop();
+        if let Some(scope) = self.scopes.pop() {
+            self.emit_scope_exit(scope.base, scope.slots > 0, scope.environment)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the locals of a block from `base` on, if it has declared any, and its environment.
+    fn emit_scope_exit(&mut self, base: usize, locals: bool, environment: bool) -> Result<()> {
+        if locals {
+            self.emit(OpCode::PopLocals);
+            self.emit_count(base)?;
+        }
+        if environment {
+            self.emit(OpCode::PopScope);
+        }
+        Ok(())
+    }
+
+    /// Emit a forward jump, returning the operand offset to patch later.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit(op);
+        self.emit_u16(u16::MAX);
+        self.chunk.code.len() - 2
+    }
+
+    /// Point a forward jump at the next instruction.
+    fn patch_jump(&mut self, offset: usize) -> Result<()> {
+        let jump = self.chunk.code.len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| CompileError::JumpTooLarge(self.line))?;
+        self.chunk.code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize) -> Result<()> {
+        self.emit(OpCode::Loop);
+        let jump = self.chunk.code.len() - start + 2;
+        let jump = u16::try_from(jump).map_err(|_| CompileError::JumpTooLarge(self.line))?;
+        self.emit_u16(jump);
+        Ok(())
+    }
+
+    /// Statements in a new scope, like `Interpreter::execute_block`.
+    fn block(&mut self, statements: &'a [Stmt]) -> Result<()> {
+        self.begin_scope(statements);
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        self.end_scope()
+    }
+
+    /// Compile a loop body, returning the jumps of its breaks.
+    fn loop_body(&mut self, body: &'a Stmt) -> Result<Vec<usize>> {
+        self.loops.push(Loop {
+            exits: self.exits.len(),
+            breaks: Vec::new(),
+        });
+        let result = self.statement(body);
+        let breaks = self.loops.pop().map(|v| v.breaks).unwrap_or_default();
+        result.map(|_| breaks)
+    }
+
+    fn patch_breaks(&mut self, breaks: Vec<usize>) -> Result<()> {
+        for jump in breaks {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Expression { expression } => {
+                self.expression(expression)?;
+                self.emit(OpCode::Pop);
+            }
+            Stmt::Print { expression } => {
+                self.expression(expression)?;
+                self.emit(OpCode::Print);
+            }
+            Stmt::VarDecl {
+                name,
+                initializer,
+                slot,
+                ..
+            } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer)?;
+                }
+                self.line = name.line;
+                match (slot, initializer) {
+                    (Some(slot), Some(_)) => self.emit_slot(OpCode::DefineLocal, name, *slot)?,
+                    (Some(slot), None) => self.emit_slot(OpCode::DeclareLocal, name, *slot)?,
+                    (None, Some(_)) => self.emit_name(OpCode::DefineVar, name)?,
+                    (None, None) => self.emit_name(OpCode::DeclareVar, name)?,
+                }
+            }
+            Stmt::ConstDecl {
+                name,
+                initializer,
+                slot,
+            } => {
+                self.expression(initializer)?;
+                self.line = name.line;
+                match slot {
+                    Some(slot) => self.emit_slot(OpCode::DefineLocal, name, *slot)?,
+                    None => self.emit_name(OpCode::DefineConst, name)?,
+                }
+            }
+            Stmt::Block { statements } => self.block(statements)?,
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition)?;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(then_branch)?;
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(end_jump)?;
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let start = self.chunk.code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                let breaks = self.loop_body(body)?;
+                self.emit_loop(start)?;
+                self.patch_jump(exit_jump)?;
+                self.emit(OpCode::Pop);
+                self.patch_breaks(breaks)?;
+            }
+            Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                let start = self.chunk.code.len();
+                let breaks = self.loop_body(body)?;
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.emit_loop(start)?;
+                self.patch_jump(exit_jump)?;
+                self.emit(OpCode::Pop);
+                self.patch_breaks(breaks)?;
+            }
+            Stmt::Loop { body } => {
+                let start = self.chunk.code.len();
+                let breaks = self.loop_body(body)?;
+                self.emit_loop(start)?;
+                self.patch_breaks(breaks)?;
+            }
+            Stmt::Break { keyword } => {
+                self.line = keyword.line;
+                // Parser rejects break outside of a loop.
+                let depth = self.loops.last().map_or(0, |v| v.exits);
+                for i in (depth..self.exits.len()).rev() {
+                    match self.exits[i] {
+                        Exit::Scope(index) => {
+                            let scope = &self.scopes[index];
+                            let (base, locals) = (scope.base, scope.slots > 0);
+                            self.emit_scope_exit(base, locals, scope.environment)?
+                        }
+                        Exit::Handler => self.emit(OpCode::PopHandler),
+                        Exit::Finally(finally, scopes) => {
+                            // The finally block runs outside of its own try statement.
+                            let inner = self.exits.split_off(i);
+                            let inner_scopes = self.scopes.split_off(scopes);
+                            self.block(finally)?;
+                            self.scopes.extend(inner_scopes);
+                            self.exits.extend(inner);
+                        }
+                    }
+                }
+                let jump = self.emit_jump(OpCode::Jump);
+                if let Some(v) = self.loops.last_mut() {
+                    v.breaks.push(jump);
+                }
+            }
+            Stmt::Import {
+                keyword,
+                path,
+                alias,
+            } => {
+                self.line = keyword.line;
+                let path = self.constant(path.literal.clone())?;
+                if let Some(alias) = alias {
+                    let alias = self.name(alias)?;
+                    self.emit(OpCode::ImportAs);
+                    self.emit_u16(path);
+                    self.emit_u16(alias);
+                } else {
+                    self.emit(OpCode::Import);
+                    self.emit_u16(path);
+                }
+            }
+            Stmt::Assert {
+                keyword,
+                condition,
+                source,
+                message,
+            } => {
+                self.line = keyword.line;
+                self.expression(condition)?;
+                let fail_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(fail_jump)?;
+                self.emit(OpCode::Pop);
+                if let Some(message) = message {
+                    self.expression(message)?;
+                }
+                self.line = keyword.line;
+                let source = self.constant(Value::String(source.as_str().into()))?;
+                self.emit(OpCode::AssertFail);
+                self.emit_u16(source);
+                self.emit_byte(message.is_some() as u8);
+                self.patch_jump(end_jump)?;
+            }
+            Stmt::Throw { keyword, value } => {
+                self.expression(value)?;
+                self.line = keyword.line;
+                self.emit(OpCode::Throw);
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => self.try_statement(body, catch.as_ref(), finally.as_deref())?,
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+                ..
+            } => {
+                self.expression(subject)?;
+                let mut end_jumps = Vec::new();
+                for arm in arms {
+                    let mut body_jumps = Vec::new();
+                    for value in &arm.values {
+                        self.emit(OpCode::Dup);
+                        self.expression(value)?;
+                        self.emit(OpCode::Equal);
+                        let next_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit(OpCode::Pop);
+                        body_jumps.push(self.emit_jump(OpCode::Jump));
+                        self.patch_jump(next_jump)?;
+                        self.emit(OpCode::Pop);
+                    }
+                    let skip_jump = self.emit_jump(OpCode::Jump);
+                    for jump in body_jumps {
+                        self.patch_jump(jump)?;
+                    }
+                    // Pop the subject.
+                    self.emit(OpCode::Pop);
+                    self.block(&arm.body)?;
+                    end_jumps.push(self.emit_jump(OpCode::Jump));
+                    self.patch_jump(skip_jump)?;
+                }
+                self.emit(OpCode::Pop);
+                if let Some(default) = default {
+                    self.block(default)?;
+                }
+                for jump in end_jumps {
+                    self.patch_jump(jump)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Caught errors wait in the VM until `Catch` takes their value or `Rethrow` raises them.
+    fn try_statement(
+        &mut self,
+        body: &'a [Stmt],
+        catch: Option<&'a CatchClause>,
+        finally: Option<&'a [Stmt]>,
+    ) -> Result<()> {
+        if let Some(finally) = finally {
+            self.exits.push(Exit::Finally(finally, self.scopes.len()));
+        }
+        let body_handler = self.protected(body)?;
+
+        let mut done_jumps = vec![self.emit_jump(OpCode::Jump)];
+        self.patch_jump(body_handler)?;
+        if let Some(catch) = catch {
+            if finally.is_some() {
+                let catch_handler = self.emit_jump(OpCode::PushHandler);
+                self.exits.push(Exit::Handler);
+                self.catch_clause(catch)?;
+                self.exits.pop();
+                self.emit(OpCode::PopHandler);
+                done_jumps.push(self.emit_jump(OpCode::Jump));
+                self.patch_jump(catch_handler)?;
+            } else {
+                self.catch_clause(catch)?;
+            }
+        }
+        if finally.is_some() {
+            self.exits.pop();
+        }
+
+        if let Some(finally) = finally {
+            // Reached with an error pending, from either the body or the catch clause.
+            self.block(finally)?;
+            self.emit(OpCode::Rethrow);
+            for jump in done_jumps {
+                self.patch_jump(jump)?;
+            }
+            self.block(finally)?;
+        } else {
+            for jump in done_jumps {
+                self.patch_jump(jump)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a block under a handler, returning the handler jump to patch.
+    fn protected(&mut self, body: &'a [Stmt]) -> Result<usize> {
+        let handler = self.emit_jump(OpCode::PushHandler);
+        self.exits.push(Exit::Handler);
+        self.block(body)?;
+        self.exits.pop();
+        self.emit(OpCode::PopHandler);
+        Ok(handler)
+    }
+
+    fn catch_clause(&mut self, catch: &'a CatchClause) -> Result<()> {
+        self.line = catch.name.line;
+        self.begin_scope(&catch.body);
+        self.emit(OpCode::Catch);
+        self.emit_slot(OpCode::DefineLocal, &catch.name, 0)?;
+        for statement in &catch.body {
+            self.statement(statement)?;
+        }
+        self.end_scope()
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Literal { value, line } => {
+                self.line = *line;
+                match value {
+                    Value::Null => self.emit(OpCode::Nil),
+                    Value::Boolean(true) => self.emit(OpCode::True),
+                    Value::Boolean(false) => self.emit(OpCode::False),
+                    value => {
+                        let index = self.constant(value.clone())?;
+                        self.emit(OpCode::Constant);
+                        self.emit_u16(index);
+                    }
+                }
+            }
+            Expr::Grouping { expression } => self.expression(expression)?,
+            Expr::Unary { operator, right } => {
+                self.expression(right)?;
+                self.line = operator.line;
+                self.emit(match operator.token_type {
+                    TokenType::Minus => OpCode::Negate,
+                    TokenType::Bang => OpCode::Not,
+                    TokenType::Typeof => OpCode::Typeof,
+                    _ => unreachable!(),
+                });
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.line = operator.line;
+                self.emit(match operator.token_type {
+                    TokenType::EqualEqual => OpCode::Equal,
+                    TokenType::BangEqual => OpCode::NotEqual,
+                    TokenType::Greater => OpCode::Greater,
+                    TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    TokenType::Less => OpCode::Less,
+                    TokenType::LessEqual => OpCode::LessEqual,
+                    TokenType::Plus => OpCode::Add,
+                    TokenType::Minus => OpCode::Subtract,
+                    TokenType::Star => OpCode::Multiply,
+                    TokenType::Slash => OpCode::Divide,
+                    _ => unreachable!(),
+                });
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                match (&operator.token_type, &**left) {
+                    (TokenType::QuestionQuestionEqual, Expr::Variable { token, binding }) => {
+                        self.variable(token, binding, true)?
+                    }
+                    _ => self.expression(left)?,
+                }
+                self.line = operator.line;
+                match operator.token_type {
+                    TokenType::Or => {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        let end_jump = self.emit_jump(OpCode::Jump);
+                        self.patch_jump(else_jump)?;
+                        self.emit(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    }
+                    TokenType::And => {
+                        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    }
+                    TokenType::QuestionQuestion | TokenType::QuestionQuestionEqual => {
+                        let end_jump = self.emit_jump(OpCode::JumpIfNotNil);
+                        self.emit(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Comma { left, right } => {
+                self.expression(left)?;
+                self.emit(OpCode::Pop);
+                self.expression(right)?;
+            }
+            Expr::Ternary {
+                condition,
+                left,
+                right,
+            } => {
+                self.expression(condition)?;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression(left)?;
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit(OpCode::Pop);
+                self.expression(right)?;
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Variable { token, binding } => self.variable(token, binding, false)?,
+            Expr::Assign {
+                name,
+                value,
+                binding,
+            } => {
+                self.expression(value)?;
+                match binding {
+                    Binding::Global => self.emit_name(OpCode::SetVar, name)?,
+                    Binding::Local { depth, slot } => {
+                        self.line = name.line;
+                        self.emit_local(OpCode::SetLocal, None, *depth, *slot)?
+                    }
+                }
+            }
+            Expr::TypeTest { value, type_name } => {
+                self.expression(value)?;
+                self.emit(OpCode::TypeTest);
+                let index = TYPES
+                    .iter()
+                    .position(|v| v == type_name)
+                    .unwrap_or_default();
+                self.emit_byte(index as u8);
+            }
+            Expr::Get { object, name } => {
+                self.expression(object)?;
+                self.emit_name(OpCode::GetProperty, name)?;
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                // Built-in methods are called directly rather than through a method value.
+                let method = if let Expr::Get { object, name } = callee.as_ref() {
+                    self.expression(object)?;
+                    self.emit_name(OpCode::GetMethod, name)?;
+                    Some(name)
+                } else {
+                    self.expression(callee)?;
+                    None
+                };
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                let count = u8::try_from(arguments.len())
+                    .map_err(|_| CompileError::TooManyValues(paren.line))?;
+                if let Some(name) = method {
+                    let index = self.name(name)?;
+                    self.line = paren.line;
+                    self.emit(OpCode::Invoke);
+                    self.emit_u16(index);
+                    for byte in name.line.to_be_bytes() {
+                        self.emit_byte(byte);
+                    }
+                } else {
+                    self.line = paren.line;
+                    self.emit(OpCode::Call);
+                }
+                self.emit_byte(count);
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.line = bracket.line;
+                self.emit(OpCode::Index);
+            }
+            Expr::Slice {
+                object,
+                bracket,
+                start,
+                end,
+            } => {
+                self.expression(object)?;
+                let mut flags = 0;
+                if let Some(start) = start {
+                    self.expression(start)?;
+                    flags |= 1;
+                }
+                if let Some(end) = end {
+                    self.expression(end)?;
+                    flags |= 2;
+                }
+                self.line = bracket.line;
+                self.emit(OpCode::Slice);
+                self.emit_byte(flags);
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.emit(OpCode::List);
+                self.emit_count(elements.len())?;
+            }
+        }
+        Ok(())
+    }
+}
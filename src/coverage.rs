@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::profiler::display_name;
+use crate::stmt::{BranchId, Stmt};
+
+/// Counts of one source file.
+struct FileCoverage {
+    path: PathBuf,
+    /// Executions of every line with a statement, run or not.
+    lines: BTreeMap<i32, u64>,
+    /// Times each way of a branching statement was taken, by line and the statement's
+    /// position among the branching statements of its line.
+    branches: BTreeMap<(i32, usize), Vec<u64>>,
+}
+
+/// Executed lines and branches per source file, for `--coverage`.
+pub struct Coverage {
+    /// lcov tracefile to write.
+    output: PathBuf,
+    files: Vec<FileCoverage>,
+    /// Files being executed, the current one last.
+    file_stack: Vec<usize>,
+    /// Branching statements registered so far: file, line and position on the line.
+    branch_ids: HashMap<BranchId, (usize, i32, usize)>,
+}
+
+/// Identity and number of ways of a statement, if it branches: an if statement runs its then
+/// branch or not, a loop condition enters the body or leaves, and a match takes one of
+/// its arms or none.
+fn branches(stmt: &Stmt) -> Option<(BranchId, usize)> {
+    match stmt {
+        Stmt::If { id, .. } | Stmt::While { id, .. } | Stmt::DoWhile { id, .. } => Some((*id, 2)),
+        Stmt::Match { id, arms, .. } => Some((*id, arms.len() + 1)),
+        _ => None,
+    }
+}
+
+impl Coverage {
+    pub fn new(output: PathBuf) -> Self {
+        Self {
+            output,
+            files: Vec::new(),
+            file_stack: Vec::new(),
+            branch_ids: HashMap::new(),
+        }
+    }
+
+    /// Start counting the statements of a file, until the matching `leave_file`.
+    /// Lines that never run are reported with a count of zero.
+    pub fn enter_file(&mut self, path: &Path, statements: &[Stmt]) {
+        let index = match self.files.iter().position(|v| v.path == path) {
+            Some(index) => index,
+            None => {
+                self.files.push(FileCoverage {
+                    path: path.to_path_buf(),
+                    lines: BTreeMap::new(),
+                    branches: BTreeMap::new(),
+                });
+                self.files.len() - 1
+            }
+        };
+        self.file_stack.push(index);
+        let mut positions = HashMap::new();
+        self.register(index, statements, &mut positions);
+    }
+
+    fn register(&mut self, file: usize, statements: &[Stmt], positions: &mut HashMap<i32, usize>) {
+        for stmt in statements {
+            if let Some(line) = stmt.line() {
+                self.files[file].lines.entry(line).or_default();
+                if let Some((id, count)) = branches(stmt) {
+                    let position = positions.entry(line).or_default();
+                    self.files[file]
+                        .branches
+                        .entry((line, *position))
+                        .or_insert_with(|| vec![0; count]);
+                    self.branch_ids.insert(id, (file, line, *position));
+                    *position += 1;
+                }
+            }
+            match stmt {
+                Stmt::Block { statements } => self.register(file, statements, positions),
+                Stmt::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    self.register(file, std::slice::from_ref(then_branch), positions);
+                    if let Some(else_branch) = else_branch {
+                        self.register(file, std::slice::from_ref(else_branch), positions);
+                    }
+                }
+                Stmt::While { body, .. } | Stmt::DoWhile { body, .. } | Stmt::Loop { body } => {
+                    self.register(file, std::slice::from_ref(body), positions)
+                }
+                Stmt::Match { arms, default, .. } => {
+                    for arm in arms {
+                        self.register(file, &arm.body, positions);
+                    }
+                    if let Some(default) = default {
+                        self.register(file, default, positions);
+                    }
+                }
+                Stmt::Try {
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    self.register(file, body, positions);
+                    if let Some(catch) = catch {
+                        self.register(file, &catch.body, positions);
+                    }
+                    if let Some(finally) = finally {
+                        self.register(file, finally, positions);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Stop counting the current file.
+    pub fn leave_file(&mut self) {
+        self.file_stack.pop();
+    }
+
+    /// Count a statement run on a line of the current file.
+    pub fn hit_line(&mut self, line: i32) {
+        if let Some(&file) = self.file_stack.last() {
+            *self.files[file].lines.entry(line).or_default() += 1;
+        }
+    }
+
+    /// Count a way a branching statement went, numbered as in `branches`.
+    pub fn hit_branch(&mut self, id: BranchId, branch: usize) {
+        if let Some(&(file, line, position)) = self.branch_ids.get(&id) {
+            if let Some(count) = self.files[file]
+                .branches
+                .get_mut(&(line, position))
+                .and_then(|v| v.get_mut(branch))
+            {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Tracefile in the lcov format read by genhtml and coverage viewers.
+    pub fn lcov(&self) -> String {
+        let mut output = String::new();
+        for file in &self.files {
+            let _ = writeln!(output, "TN:\nSF:{}", file.path.display());
+            let (mut found, mut hit) = (0, 0);
+            for (&(line, position), counts) in &file.branches {
+                let line_run = file.lines.get(&line).is_some_and(|v| *v > 0);
+                for (branch, count) in counts.iter().enumerate() {
+                    // `-` marks a branch whose statement never ran.
+                    let taken = if line_run {
+                        count.to_string()
+                    } else {
+                        "-".to_string()
+                    };
+                    let _ = writeln!(output, "BRDA:{line},{position},{branch},{taken}");
+                    found += 1;
+                    hit += usize::from(*count > 0);
+                }
+            }
+            let _ = writeln!(output, "BRF:{found}\nBRH:{hit}");
+            for (line, count) in &file.lines {
+                let _ = writeln!(output, "DA:{line},{count}");
+            }
+            let lines_hit = file.lines.values().filter(|v| **v > 0).count();
+            let _ = writeln!(output, "LF:{}\nLH:{lines_hit}", file.lines.len());
+            output.push_str("end_of_record\n");
+        }
+        output
+    }
+
+    /// Lines and branches run per file, with the total.
+    pub fn summary(&self) -> String {
+        let mut output = String::new();
+        let percent = |hit: usize, found: usize| {
+            if found == 0 {
+                100.0
+            } else {
+                hit as f64 * 100.0 / found as f64
+            }
+        };
+        let row = |output: &mut String, name: &str, lines: (usize, usize), branches| {
+            let (branch_hit, branch_found) = branches;
+            let _ = writeln!(
+                output,
+                "{name:<32} {:>6.1}% of {:>4} lines {:>6.1}% of {:>4} branches",
+                percent(lines.0, lines.1),
+                lines.1,
+                percent(branch_hit, branch_found),
+                branch_found
+            );
+        };
+
+        let (mut lines, mut branches) = ((0, 0), (0, 0));
+        for file in &self.files {
+            let file_lines = (
+                file.lines.values().filter(|v| **v > 0).count(),
+                file.lines.len(),
+            );
+            let counts = file.branches.values().flatten();
+            let file_branches = (counts.clone().filter(|v| **v > 0).count(), counts.count());
+            row(
+                &mut output,
+                &display_name(&file.path),
+                file_lines,
+                file_branches,
+            );
+            lines = (lines.0 + file_lines.0, lines.1 + file_lines.1);
+            branches = (branches.0 + file_branches.0, branches.1 + file_branches.1);
+        }
+        row(&mut output, "total", lines, branches);
+        output
+    }
+
+    /// Write the tracefile and print the summary.
+    pub fn finish(&self) -> io::Result<()> {
+        fs::write(&self.output, self.lcov())?;
+        eprint!("{}", self.summary());
+        Ok(())
+    }
+}
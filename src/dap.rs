@@ -0,0 +1,466 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Read, StdinLock, Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+
+use crate::debugger::{file_name, scope_chain, show_value, Debugger, Mode, Pause, Stepping};
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::json::{object, Json};
+use crate::natives::Host;
+use crate::token::Value;
+use crate::typecheck::Type;
+
+/// Programs run in a single thread, reported to clients with this id.
+const THREAD_ID: i64 = 1;
+
+/// Largest message body read, in bytes; longer ones are skipped without being buffered.
+const MAX_MESSAGE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Debug Adapter Protocol messages over stdin and stdout: a Content-Length header, a blank
+/// line, then a JSON body.
+struct Connection {
+    input: StdinLock<'static>,
+    output: Stdout,
+    /// Sequence number of the next message sent.
+    seq: i64,
+}
+
+fn command(request: &Json) -> &str {
+    request.get("command").and_then(Json::as_str).unwrap_or("")
+}
+
+fn arguments(request: &Json) -> &Json {
+    request.get("arguments").unwrap_or(&Json::Null)
+}
+
+impl Connection {
+    fn new() -> Self {
+        Self {
+            input: io::stdin().lock(),
+            output: io::stdout(),
+            seq: 1,
+        }
+    }
+
+    /// Next message from the client; None once it closes the connection. Malformed
+    /// messages are reported on stderr and skipped.
+    fn read(&mut self) -> Option<Json> {
+        loop {
+            let mut length = None;
+            loop {
+                let mut header = String::new();
+                if self.input.read_line(&mut header).ok()? == 0 {
+                    return None;
+                }
+                let header = header.trim();
+                if header.is_empty() && length.is_some() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("Content-Length") {
+                        length = value.trim().parse::<usize>().ok();
+                    }
+                }
+            }
+            let length = length?;
+            if length > MAX_MESSAGE_LENGTH {
+                eprintln!(
+                    "Ignoring message of {length} bytes, over the limit of {MAX_MESSAGE_LENGTH}."
+                );
+                let skipped = io::copy(&mut (&mut self.input).take(length as u64), &mut io::sink());
+                if skipped.ok()? < length as u64 {
+                    return None;
+                }
+                continue;
+            }
+            let mut body = vec![0; length];
+            self.input.read_exact(&mut body).ok()?;
+            match Json::parse(&String::from_utf8_lossy(&body)) {
+                Ok(message) => return Some(message),
+                Err(e) => eprintln!("Ignoring malformed message: {e}"),
+            }
+        }
+    }
+
+    /// Send a message; a client that went away is noticed when reading.
+    fn send(&mut self, kind: &str, members: Vec<(&str, Json)>) {
+        let header = [("seq", self.seq.into()), ("type", kind.into())];
+        self.seq += 1;
+        let body = object(header.into_iter().chain(members)).to_string();
+        let _ = write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len());
+        let _ = self.output.flush();
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) {
+        let mut members = vec![
+            (
+                "request_seq",
+                request.get("seq").cloned().unwrap_or(Json::Null),
+            ),
+            ("success", result.is_ok().into()),
+            ("command", command(request).into()),
+        ];
+        match result {
+            Ok(body) => members.push(("body", body)),
+            Err(message) => members.push(("message", message.into())),
+        }
+        self.send("response", members);
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send("event", vec![("event", event.into()), ("body", body)]);
+    }
+
+    /// Answer a request that does not need a paused program, returning whether it was one.
+    fn answer(&mut self, stepping: &mut Stepping, request: &Json) -> bool {
+        let result = match command(request) {
+            "threads" => Ok(object([(
+                "threads",
+                vec![object([("id", THREAD_ID.into()), ("name", "main".into())])].into(),
+            )])),
+            "setBreakpoints" => set_breakpoints(stepping, arguments(request)),
+            "disconnect" => {
+                self.respond(request, Ok(object([])));
+                process::exit(0);
+            }
+            _ => return false,
+        };
+        self.respond(request, result);
+        true
+    }
+}
+
+/// Replace the breakpoints of a source file.
+fn set_breakpoints(stepping: &mut Stepping, arguments: &Json) -> Result<Json, String> {
+    let path = arguments
+        .get("source")
+        .and_then(|v| v.get("path"))
+        .and_then(Json::as_str)
+        .ok_or("Breakpoints need a source path.")?;
+    let path = Path::new(path);
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    stepping.breakpoints.retain(|v| v.0.as_ref() != Some(&path));
+    let requested = arguments
+        .get("breakpoints")
+        .and_then(Json::as_array)
+        .unwrap_or_default();
+    let mut breakpoints = Vec::new();
+    for breakpoint in requested {
+        let line = breakpoint.get("line").and_then(Json::as_i64).unwrap_or(0) as i32;
+        stepping.breakpoints.push((Some(path.clone()), line));
+        breakpoints.push(object([("verified", true.into()), ("line", line.into())]));
+    }
+    Ok(object([("breakpoints", breakpoints.into())]))
+}
+
+/// Something whose contents a client can expand while the program is paused.
+#[derive(Clone)]
+enum Reference {
+    /// A scope, or the top level of a module value.
+    Scope(Rc<RefCell<Environment>>),
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+/// Debugger reporting pauses to a client and answering its requests about them.
+struct Adapter {
+    connection: Rc<RefCell<Connection>>,
+    stepping: Stepping,
+    /// Contents handed out while paused; a variables reference is an index from 1.
+    references: Vec<Reference>,
+}
+
+impl Adapter {
+    fn reference(&mut self, reference: Reference) -> usize {
+        self.references.push(reference);
+        self.references.len()
+    }
+
+    /// Members describing a value: shown text, type and reference to its contents if any.
+    fn describe(&mut self, value: Option<&Value>, key: &'static str) -> Vec<(&'static str, Json)> {
+        let Some(value) = value else {
+            return vec![
+                (key, "<uninitialized>".into()),
+                ("variablesReference", 0.into()),
+            ];
+        };
+        let contents = match value {
+            Value::List(list) => self.reference(Reference::List(list.clone())),
+            Value::Module(_, environment) => self.reference(Reference::Scope(environment.clone())),
+            _ => 0,
+        };
+        vec![
+            (key, show_value(value).into()),
+            ("type", Type::of(value).to_string().into()),
+            ("variablesReference", contents.into()),
+        ]
+    }
+
+    fn frame_environment(
+        interpreter: &Interpreter,
+        arguments: &Json,
+    ) -> Result<Rc<RefCell<Environment>>, String> {
+        let Some(id) = arguments.get("frameId").and_then(Json::as_i64) else {
+            return Ok(interpreter.environment().clone());
+        };
+        usize::try_from(id - 1)
+            .ok()
+            .and_then(|index| interpreter.frames().into_iter().nth(index))
+            .map(|frame| frame.environment)
+            .ok_or_else(|| format!("Unknown frame {id}."))
+    }
+
+    fn stack_trace(interpreter: &Interpreter) -> Json {
+        let frames: Vec<Json> = interpreter
+            .frames()
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let name = file_name(frame.path.as_deref());
+                let mut members = vec![
+                    ("id", (index + 1).into()),
+                    ("name", name.clone().into()),
+                    ("line", frame.line.into()),
+                    ("column", 1.into()),
+                ];
+                if let Some(path) = &frame.path {
+                    let path = path.display().to_string();
+                    members.push((
+                        "source",
+                        object([("name", name.into()), ("path", path.into())]),
+                    ));
+                }
+                object(members)
+            })
+            .collect();
+        let total = frames.len();
+        object([
+            ("stackFrames", frames.into()),
+            ("totalFrames", total.into()),
+        ])
+    }
+
+    fn scopes(&mut self, interpreter: &Interpreter, arguments: &Json) -> Result<Json, String> {
+        let environment = Self::frame_environment(interpreter, arguments)?;
+        let scopes: Vec<Json> = scope_chain(&environment)
+            .into_iter()
+            .map(|(kind, environment)| {
+                let reference = self.reference(Reference::Scope(environment));
+                object([
+                    ("name", kind.into()),
+                    ("variablesReference", reference.into()),
+                    ("expensive", (kind == "built-ins").into()),
+                ])
+            })
+            .collect();
+        Ok(object([("scopes", scopes.into())]))
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let id = arguments
+            .get("variablesReference")
+            .and_then(Json::as_i64)
+            .unwrap_or(0);
+        let reference = usize::try_from(id - 1)
+            .ok()
+            .and_then(|index| self.references.get(index).cloned())
+            .ok_or_else(|| format!("Unknown variables reference {id}."))?;
+        let contents: Vec<(String, Option<Value>)> = match reference {
+            Reference::Scope(environment) => environment
+                .borrow()
+                .variables()
+                .into_iter()
+                .map(|(name, _, value)| (name.to_string(), value))
+                .collect(),
+            Reference::List(list) => list
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (index.to_string(), Some(value.clone())))
+                .collect(),
+        };
+        let variables: Vec<Json> = contents
+            .into_iter()
+            .map(|(name, value)| {
+                let mut members = vec![("name", name.into())];
+                members.extend(self.describe(value.as_ref(), "value"));
+                object(members)
+            })
+            .collect();
+        Ok(object([("variables", variables.into())]))
+    }
+
+    fn evaluate(
+        &mut self,
+        interpreter: &mut Interpreter,
+        arguments: &Json,
+    ) -> Result<Json, String> {
+        let expression = arguments
+            .get("expression")
+            .and_then(Json::as_str)
+            .ok_or("Nothing to evaluate.")?;
+        let environment = Self::frame_environment(interpreter, arguments)?;
+        let value = interpreter.evaluate_source(expression, environment)?;
+        Ok(object(self.describe(Some(&value), "result")))
+    }
+
+    /// Answer requests about the paused program until one resumes it.
+    fn paused(&mut self, interpreter: &mut Interpreter, connection: &mut Connection, depth: usize) {
+        loop {
+            let Some(request) = connection.read() else {
+                process::exit(0);
+            };
+            if connection.answer(&mut self.stepping, &request) {
+                continue;
+            }
+            let resume = match command(&request) {
+                "continue" => Some(Mode::Continue),
+                "next" => Some(Mode::Next(depth)),
+                "stepIn" => Some(Mode::Step),
+                "stepOut" => Some(Mode::Out(depth)),
+                _ => None,
+            };
+            if let Some(mode) = resume {
+                self.stepping.mode = mode;
+                self.references.clear();
+                let body = match command(&request) {
+                    "continue" => object([("allThreadsContinued", true.into())]),
+                    _ => object([]),
+                };
+                connection.respond(&request, Ok(body));
+                return;
+            }
+            let arguments = arguments(&request);
+            let result = match command(&request) {
+                "stackTrace" => Ok(Self::stack_trace(interpreter)),
+                "scopes" => self.scopes(interpreter, arguments),
+                "variables" => self.variables(arguments),
+                "evaluate" => self.evaluate(interpreter, arguments),
+                // Already paused.
+                "pause" => Ok(object([])),
+                command => Err(format!("Unsupported request '{command}'.")),
+            };
+            connection.respond(&request, result);
+        }
+    }
+
+    fn output(&mut self, category: &str, text: &str) {
+        self.connection.borrow_mut().event(
+            "output",
+            object([
+                ("category", category.into()),
+                ("output", format!("{text}\n").into()),
+            ]),
+        );
+    }
+}
+
+impl Debugger for Adapter {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, line: i32, depth: usize) {
+        let here = (interpreter.frames().swap_remove(0).path, line);
+        let Some(pause) = self.stepping.pause(&here, depth) else {
+            return;
+        };
+        let reason = match pause {
+            Pause::Entry => "entry",
+            Pause::Breakpoint => "breakpoint",
+            Pause::Step => "step",
+        };
+        let connection = self.connection.clone();
+        let mut connection = connection.borrow_mut();
+        connection.event(
+            "stopped",
+            object([
+                ("reason", reason.into()),
+                ("threadId", THREAD_ID.into()),
+                ("allThreadsStopped", true.into()),
+            ]),
+        );
+        self.paused(interpreter, &mut connection, depth);
+    }
+
+    fn print(&mut self, text: &str) {
+        self.output("stdout", text);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.output("stderr", message);
+    }
+}
+
+/// Serve the Debug Adapter Protocol on stdin and stdout for `warlox dap`: configure and
+/// launch one program, run it on the tree walk interpreter, then wait for the client to
+/// disconnect.
+pub fn serve(mut host: Host) {
+    host.deny_stdin();
+    let connection = Rc::new(RefCell::new(Connection::new()));
+    let mut stepping = Stepping::new(Mode::Continue);
+    let mut program: Option<PathBuf> = None;
+    let mut configured = false;
+    while program.is_none() || !configured {
+        let mut connection = connection.borrow_mut();
+        let Some(request) = connection.read() else {
+            return;
+        };
+        if connection.answer(&mut stepping, &request) {
+            continue;
+        }
+        let arguments = arguments(&request);
+        let result = match command(&request) {
+            "initialize" => {
+                let capabilities = object([
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsEvaluateForHovers", true.into()),
+                ]);
+                connection.respond(&request, Ok(capabilities));
+                connection.event("initialized", object([]));
+                continue;
+            }
+            "launch" => match arguments.get("program").and_then(Json::as_str) {
+                Some(path) if Path::new(path).is_file() => {
+                    program = Some(PathBuf::from(path));
+                    if arguments.get("stopOnEntry").and_then(Json::as_bool) == Some(true) {
+                        stepping.mode = Mode::Step;
+                    }
+                    Ok(object([]))
+                }
+                Some(path) => Err(format!("Cannot find program '{path}'.")),
+                None => Err("Launch needs a program.".to_string()),
+            },
+            "configurationDone" => {
+                configured = true;
+                Ok(object([]))
+            }
+            command => Err(format!("Unsupported request '{command}' before launch.")),
+        };
+        connection.respond(&request, result);
+    }
+
+    let mut interpreter = Interpreter::with_host(host);
+    interpreter.enable_debugger(Box::new(Adapter {
+        connection: connection.clone(),
+        stepping,
+        references: Vec::new(),
+    }));
+    let program = program.unwrap_or_default();
+    let result = crate::run_file(interpreter, &program, false);
+    let mut connection = connection.borrow_mut();
+    if let Err(e) = result {
+        let output = format!("Could not run {}: {e}\n", program.display());
+        connection.event(
+            "output",
+            object([("category", "stderr".into()), ("output", output.into())]),
+        );
+    }
+    connection.event("terminated", object([]));
+    connection.event("exited", object([("exitCode", 0.into())]));
+
+    // Nothing is left to inspect; wait for the client to disconnect.
+    let mut stepping = Stepping::new(Mode::Continue);
+    while let Some(request) = connection.read() {
+        if !connection.answer(&mut stepping, &request) {
+            connection.respond(&request, Err("The program has exited.".to_string()));
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::profiler::display_name;
+use crate::token::Value;
+
+/// Gets control before every statement that has a line, while debugging.
+pub trait Debugger {
+    /// Called before a statement on `line` of the innermost file runs. `depth` counts the
+    /// statements being executed, this one included, so the body of a loop is deeper than
+    /// the loop.
+    fn before_statement(&mut self, interpreter: &mut Interpreter, line: i32, depth: usize);
+
+    /// Show a value written by a print statement.
+    fn print(&mut self, text: &str) {
+        println!("{text}");
+    }
+
+    /// Show the error that stopped a top-level statement.
+    fn error(&mut self, message: &str) {
+        eprintln!("{message}");
+    }
+}
+
+/// A place in the source: a file, None for lines typed at the prompt, and a line.
+pub type Location = (Option<PathBuf>, i32);
+
+/// Name of a location's file in messages.
+pub fn file_name(path: Option<&Path>) -> String {
+    path.map(display_name)
+        .unwrap_or_else(|| "<prompt>".to_string())
+}
+
+/// A value as shown by a debugger: like `print`, with strings quoted.
+pub fn show_value(value: &Value) -> String {
+    match value {
+        Value::String(v) => format!("{v:?}"),
+        v => v.to_string(),
+    }
+}
+
+/// A scope and every one enclosing it, innermost first, labeled by kind: block scopes,
+/// then the top level of the file, then the built-ins.
+pub fn scope_chain(
+    environment: &Rc<RefCell<Environment>>,
+) -> Vec<(&'static str, Rc<RefCell<Environment>>)> {
+    let mut environments = Vec::new();
+    let mut next = Some(environment.clone());
+    while let Some(environment) = next {
+        next = environment.borrow().enclosing();
+        environments.push(environment);
+    }
+    let count = environments.len();
+    environments
+        .into_iter()
+        .enumerate()
+        .map(|(index, environment)| {
+            let kind = match count - index {
+                1 => "built-ins",
+                2 => "top level",
+                _ => "block",
+            };
+            (kind, environment)
+        })
+        .collect()
+}
+
+/// When to pause next, besides at breakpoints.
+pub enum Mode {
+    /// Before the next statement.
+    Step,
+    /// Before the next statement at most this deep, running nested ones through.
+    Next(usize),
+    /// Before the next statement less deep than this, finishing the enclosing one.
+    Out(usize),
+    /// Only at breakpoints.
+    Continue,
+}
+
+/// Why a debugger pauses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pause {
+    /// Before the first statement.
+    Entry,
+    Breakpoint,
+    /// As asked by the last step command.
+    Step,
+}
+
+/// Breakpoints and stepping state shared by the debugger front ends.
+pub struct Stepping {
+    pub mode: Mode,
+    pub breakpoints: Vec<Location>,
+    /// Location of the previous statement, so a breakpoint pauses once each time its line
+    /// is reached rather than before every statement on it.
+    last: Option<Location>,
+}
+
+impl Stepping {
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            breakpoints: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// Whether to pause before a statement at `here`, `depth` statements deep, and why.
+    pub fn pause(&mut self, here: &Location, depth: usize) -> Option<Pause> {
+        let first = self.last.is_none();
+        let at_breakpoint = self.last.as_ref() != Some(here) && self.breakpoints.contains(here);
+        self.last = Some(here.clone());
+        let stepped = match self.mode {
+            Mode::Step => true,
+            Mode::Next(next) => depth <= next,
+            Mode::Out(out) => depth < out,
+            Mode::Continue => false,
+        };
+        match (first, at_breakpoint, stepped) {
+            (true, _, true) => Some(Pause::Entry),
+            (_, true, _) => Some(Pause::Breakpoint),
+            (_, _, true) => Some(Pause::Step),
+            _ => None,
+        }
+    }
+}
+
+const HELP: &str = "\
+break [FILE:]LINE   (b) pause before the statements of a line, in the current file by default
+delete [FILE:]LINE  (d) remove a breakpoint
+step                (s) run to the next statement
+next                (n) run to the next statement, running nested ones through
+continue            (c) run to the next breakpoint
+env                 (e) show the variables of every scope
+print EXPR          (p) evaluate an expression in the current scope
+watch EXPR          (w) evaluate an expression at every pause
+unwatch N               stop watching expression N
+info                (i) list breakpoints and watched expressions
+where                   show the files being run, innermost first
+quit                (q) stop the program
+An empty line repeats step or next.";
+
+/// Debugger for `--debug`, driven by commands read from stdin; everything it shows goes
+/// to stderr so the program's output stays apart.
+pub struct TerminalDebugger {
+    stepping: Stepping,
+    watches: Vec<String>,
+    /// Previous command, repeated by an empty line if it was step or next.
+    repeat: Option<String>,
+    /// Lines of source files, read when first shown.
+    sources: HashMap<PathBuf, Vec<String>>,
+}
+
+impl TerminalDebugger {
+    /// Start paused before the first statement.
+    pub fn new() -> Self {
+        Self {
+            stepping: Stepping::new(Mode::Step),
+            watches: Vec::new(),
+            repeat: None,
+            sources: HashMap::new(),
+        }
+    }
+
+    fn show_location(&mut self, (path, line): &Location) {
+        let source = path.as_ref().and_then(|path| {
+            let lines = self.sources.entry(path.clone()).or_insert_with(|| {
+                fs::read_to_string(path)
+                    .map(|v| v.lines().map(str::to_string).collect())
+                    .unwrap_or_default()
+            });
+            lines.get(usize::try_from(*line - 1).ok()?)
+        });
+        let name = file_name(path.as_deref());
+        match source {
+            Some(source) => eprintln!("{name}:{line}: {}", source.trim()),
+            None => eprintln!("{name}:{line}"),
+        }
+    }
+
+    fn show_watches(&self, interpreter: &mut Interpreter) {
+        for (index, source) in self.watches.iter().enumerate() {
+            let environment = interpreter.environment().clone();
+            match interpreter.evaluate_source(source, environment) {
+                Ok(value) => eprintln!("  {}: {source} = {}", index + 1, show_value(&value)),
+                Err(e) => eprintln!("  {}: {source}: {e}", index + 1),
+            }
+        }
+    }
+
+    /// Parse `[FILE:]LINE`, defaulting to the file being run.
+    fn location(argument: &str, current: &Option<PathBuf>) -> Option<Location> {
+        match argument.rsplit_once(':') {
+            Some((file, line)) => {
+                let path = Path::new(file);
+                let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                Some((Some(path), line.trim().parse().ok()?))
+            }
+            None => Some((current.clone(), argument.parse().ok()?)),
+        }
+    }
+
+    /// Read and run commands until one resumes the program.
+    fn prompt(&mut self, interpreter: &mut Interpreter, here: &Location, depth: usize) {
+        loop {
+            eprint!("(debug) ");
+            let _ = io::stderr().flush();
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+                // End of input: let the program run to the end.
+                eprintln!();
+                self.stepping.breakpoints.clear();
+                self.stepping.mode = Mode::Continue;
+                return;
+            }
+            let mut input = input.trim().to_string();
+            if input.is_empty() {
+                match &self.repeat {
+                    Some(previous) => input = previous.clone(),
+                    None => continue,
+                }
+            }
+            let (command, argument) = input.split_once(' ').unwrap_or((&input, ""));
+            let argument = argument.trim();
+            self.repeat = None;
+            match command {
+                "s" | "step" => {
+                    self.stepping.mode = Mode::Step;
+                    self.repeat = Some(input.clone());
+                    return;
+                }
+                "n" | "next" => {
+                    self.stepping.mode = Mode::Next(depth);
+                    self.repeat = Some(input.clone());
+                    return;
+                }
+                "c" | "continue" => {
+                    self.stepping.mode = Mode::Continue;
+                    return;
+                }
+                "b" | "break" => match Self::location(argument, &here.0) {
+                    Some(location) => {
+                        eprintln!(
+                            "Breakpoint at {}:{}",
+                            file_name(location.0.as_deref()),
+                            location.1
+                        );
+                        if !self.stepping.breakpoints.contains(&location) {
+                            self.stepping.breakpoints.push(location);
+                        }
+                    }
+                    None => eprintln!("Usage: break [FILE:]LINE"),
+                },
+                "d" | "delete" => match Self::location(argument, &here.0) {
+                    Some(location) if self.stepping.breakpoints.contains(&location) => {
+                        self.stepping.breakpoints.retain(|v| *v != location)
+                    }
+                    Some(_) => eprintln!("No breakpoint there."),
+                    None => eprintln!("Usage: delete [FILE:]LINE"),
+                },
+                "e" | "env" => {
+                    for (kind, environment) in scope_chain(interpreter.environment()) {
+                        let variables = environment.borrow().variables();
+                        if kind == "built-ins" {
+                            eprintln!("{kind}: {} names", variables.len());
+                            continue;
+                        }
+                        eprintln!("{kind}:");
+                        for (name, _, value) in variables {
+                            match value {
+                                Some(value) => eprintln!("  {name} = {}", show_value(&value)),
+                                None => eprintln!("  {name} (uninitialized)"),
+                            }
+                        }
+                    }
+                }
+                "p" | "print" if !argument.is_empty() => {
+                    let environment = interpreter.environment().clone();
+                    match interpreter.evaluate_source(argument, environment) {
+                        Ok(value) => eprintln!("{}", show_value(&value)),
+                        Err(e) => eprintln!("{e}"),
+                    }
+                }
+                "w" | "watch" if !argument.is_empty() => {
+                    self.watches.push(argument.to_string());
+                    self.show_watches(interpreter);
+                }
+                "unwatch" => match argument.parse::<usize>() {
+                    Ok(index) if (1..=self.watches.len()).contains(&index) => {
+                        self.watches.remove(index - 1);
+                    }
+                    _ => eprintln!("Usage: unwatch N, with N from info"),
+                },
+                "i" | "info" => {
+                    for (path, line) in &self.stepping.breakpoints {
+                        eprintln!("Breakpoint at {}:{line}", file_name(path.as_deref()));
+                    }
+                    self.show_watches(interpreter);
+                }
+                "where" => {
+                    for (index, frame) in interpreter.frames().iter().enumerate() {
+                        let name = file_name(frame.path.as_deref());
+                        eprintln!("#{index} {name}:{}", frame.line);
+                    }
+                }
+                "q" | "quit" => process::exit(0),
+                "h" | "help" => eprintln!("{HELP}"),
+                _ => eprintln!("Unknown command '{input}'; type help for a list."),
+            }
+        }
+    }
+}
+
+impl Debugger for TerminalDebugger {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, line: i32, depth: usize) {
+        let here = (interpreter.frames().swap_remove(0).path, line);
+        let Some(pause) = self.stepping.pause(&here, depth) else {
+            return;
+        };
+        if pause == Pause::Entry {
+            eprintln!("Paused before the first statement; type help for commands.");
+        }
+        self.show_location(&here);
+        self.show_watches(interpreter);
+        self.prompt(interpreter, &here, depth);
+    }
+}
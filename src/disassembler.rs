@@ -0,0 +1,143 @@
+use std::fmt::Write;
+
+use crate::chunk::{Chunk, OpCode, TYPES};
+use crate::compiler;
+use crate::interpreter::InterpreterLike;
+use crate::optimizer;
+use crate::resolver;
+use crate::stmt::Stmt;
+
+/// Name of an opcode in listings, e.g. `OP_JUMP_IF_FALSE`.
+fn op_name(op: OpCode) -> String {
+    let mut name = "OP".to_string();
+    for c in format!("{op:?}").chars() {
+        if c.is_uppercase() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+    }
+    name
+}
+
+/// List every instruction of a chunk under a `== name ==` header.
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
+    let mut output = format!("== {name} ==\n");
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(chunk, offset, &mut output);
+    }
+    output
+}
+
+/// Write one instruction as a line of the listing, returning the offset of the next one.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, output: &mut String) -> usize {
+    let _ = write!(output, "{offset:04} ");
+    if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        output.push_str("   | ");
+    } else {
+        let _ = write!(output, "{:4} ", chunk.lines[offset]);
+    }
+
+    let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+        let _ = writeln!(output, "Unknown opcode {}", chunk.code[offset]);
+        return offset + 1;
+    };
+    let name = op_name(op);
+    let constant = |index: u16| format!("{index:4} '{}'", chunk.constants[index as usize]);
+    let (operands, length) = match op {
+        OpCode::Constant
+        | OpCode::DefineVar
+        | OpCode::DeclareVar
+        | OpCode::DefineConst
+        | OpCode::GetVar
+        | OpCode::GetVarOrNil
+        | OpCode::SetVar
+        | OpCode::GetProperty
+        | OpCode::GetMethod
+        | OpCode::Import => (constant(chunk.read_u16(offset + 1)), 3),
+        OpCode::DefineLocal | OpCode::DeclareLocal | OpCode::GetLocal | OpCode::GetLocalOrNil => (
+            format!(
+                "{} @{}",
+                constant(chunk.read_u16(offset + 1)),
+                chunk.read_u16(offset + 3)
+            ),
+            5,
+        ),
+        OpCode::SetLocal | OpCode::PopLocals => (format!("@{}", chunk.read_u16(offset + 1)), 3),
+        OpCode::ImportAs => (
+            format!(
+                "{} {}",
+                constant(chunk.read_u16(offset + 1)),
+                constant(chunk.read_u16(offset + 3))
+            ),
+            5,
+        ),
+        OpCode::AssertFail => {
+            let message = if chunk.code[offset + 3] != 0 {
+                " with message"
+            } else {
+                ""
+            };
+            (
+                format!("{}{message}", constant(chunk.read_u16(offset + 1))),
+                4,
+            )
+        }
+        OpCode::Invoke => (
+            format!(
+                "({} args) {} line {}",
+                chunk.code[offset + 7],
+                constant(chunk.read_u16(offset + 1)),
+                chunk.read_i32(offset + 3)
+            ),
+            8,
+        ),
+        OpCode::Call | OpCode::Slice => (format!("{:4}", chunk.code[offset + 1]), 2),
+        OpCode::TypeTest => {
+            let index = chunk.code[offset + 1];
+            (format!("{index:4} '{}'", TYPES[index as usize]), 2)
+        }
+        OpCode::List => (format!("{:4}", chunk.read_u16(offset + 1)), 3),
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfNotNil | OpCode::PushHandler => {
+            let target = offset + 3 + chunk.read_u16(offset + 1) as usize;
+            (format!("{offset:4} -> {target}"), 3)
+        }
+        OpCode::Loop => {
+            let target = offset + 3 - chunk.read_u16(offset + 1) as usize;
+            (format!("{offset:4} -> {target}"), 3)
+        }
+        _ => (String::new(), 1),
+    };
+    if operands.is_empty() {
+        let _ = writeln!(output, "{name}");
+    } else {
+        let _ = writeln!(output, "{name:<16} {operands}");
+    }
+    offset + length
+}
+
+/// Print the bytecode a program compiles to instead of running it.
+pub struct Disassembler;
+
+impl InterpreterLike for Disassembler {
+    fn new() -> Self {
+        Disassembler {}
+    }
+
+    fn interpret(&mut self, statements: &[Stmt]) {
+        // Each top-level statement is compiled to a chunk of its own, as the VM runs it.
+        for (i, statement) in statements.iter().enumerate() {
+            match compiler::compile(std::slice::from_ref(statement)) {
+                Ok(chunk) => print!(
+                    "{}",
+                    disassemble_chunk(&chunk, &format!("statement {}", i + 1))
+                ),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+
+    fn optimize(&self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        resolver::resolve(optimizer::optimize(statements))
+    }
+}
@@ -1,13 +1,19 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::error::RuntimeError;
-use crate::token::{Token, Value};
+use crate::token::Value;
 use std::rc::Rc;
 
+#[derive(Debug)]
 pub struct Environment {
     enclosing: Option<Rc<RefCell<Environment>>>,
-    values: HashMap<String, Option<Value>>,
+    /// Variables looked up by name: the top level, built-ins and imported names.
+    values: HashMap<Rc<str>, Option<Value>>,
+    constants: HashSet<Rc<str>>,
+    /// Block locals with their names, at the slots given by the resolver.
+    slots: Vec<(Rc<str>, Option<Value>)>,
+    constant_slots: HashSet<usize>,
 }
 
 impl Environment {
@@ -15,52 +21,191 @@ impl Environment {
         Self {
             enclosing,
             values: HashMap::new(),
+            constants: HashSet::new(),
+            slots: Vec::new(),
+            constant_slots: HashSet::new(),
         }
     }
 
-    pub fn define(&mut self, name: String, value: Option<Value>) {
+    pub fn define(&mut self, name: Rc<str>, value: Option<Value>) {
+        self.constants.remove(&name);
         self.values.insert(name, value);
     }
 
-    pub fn get(&self, token: &Token) -> Result<Value, RuntimeError> {
-        match self.values.get(&token.lexeme) {
+    /// Define an immutable binding; it must be initialized right away.
+    pub fn define_constant(&mut self, name: Rc<str>, value: Value) {
+        self.constants.insert(name.clone());
+        self.values.insert(name, Some(value));
+    }
+
+    /// Define a block local; None declares it without a value.
+    pub fn define_slot(&mut self, slot: usize, name: &Rc<str>, value: Option<Value>) {
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, (name.clone(), None));
+        }
+        self.slots[slot] = (name.clone(), value);
+        self.constant_slots.remove(&slot);
+    }
+
+    /// Define an immutable block local.
+    pub fn define_constant_slot(&mut self, slot: usize, name: &Rc<str>, value: Value) {
+        self.define_slot(slot, name, Some(value));
+        self.constant_slots.insert(slot);
+    }
+
+    /// Read a block local `depth` environments out, named for errors.
+    pub fn get_at(
+        &self,
+        depth: usize,
+        slot: usize,
+        name: &str,
+        line: i32,
+    ) -> Result<Value, RuntimeError> {
+        if depth > 0 {
+            return self.ancestor().borrow().get_at(depth - 1, slot, name, line);
+        }
+        match self.slots.get(slot) {
+            Some((_, Some(v))) => Ok(v.clone()),
+            // Challenge 8.2: don't allow use of uninitialized variable.
+            _ => Err(RuntimeError::UninitializedVariable(name.to_string(), line)),
+        }
+    }
+
+    /// Assign to a block local `depth` environments out, named for errors. The parser
+    /// rejects assignments to constants it can see, but not e.g. one typed at a debugger.
+    pub fn assign_at(
+        &mut self,
+        depth: usize,
+        slot: usize,
+        name: &str,
+        line: i32,
+        value: Option<Value>,
+    ) -> Result<(), RuntimeError> {
+        if depth > 0 {
+            return self
+                .ancestor()
+                .borrow_mut()
+                .assign_at(depth - 1, slot, name, line, value);
+        }
+        if self.constant_slots.contains(&slot) {
+            return Err(RuntimeError::AssignToConstant(name.to_string(), line));
+        }
+        // Declarations run before any use the resolver points at them.
+        self.slots[slot].1 = value;
+        Ok(())
+    }
+
+    fn ancestor(&self) -> &Rc<RefCell<Environment>> {
+        self.enclosing
+            .as_ref()
+            .expect("resolver depth exceeds the environment chain")
+    }
+
+    /// Copy every binding of this scope (not the enclosing ones) into another environment.
+    pub fn export_into(&self, target: &mut Environment) {
+        for (name, value) in &self.values {
+            match value {
+                Some(value) if self.constants.contains(name) => {
+                    target.define_constant(name.clone(), value.clone())
+                }
+                _ => target.define(name.clone(), value.clone()),
+            }
+        }
+    }
+
+    /// Variables of this scope (not the enclosing ones), for debuggers: block locals in
+    /// slot order with their slot, then the others sorted by name.
+    pub fn variables(&self) -> Vec<(Rc<str>, Option<usize>, Option<Value>)> {
+        let mut named: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), None, value.clone()))
+            .collect();
+        named.sort_by(|a, b| a.0.cmp(&b.0));
+        self.slots
+            .iter()
+            .enumerate()
+            .map(|(slot, (name, value))| (name.clone(), Some(slot), value.clone()))
+            .chain(named)
+            .collect()
+    }
+
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    /// Look up a variable used on the given line.
+    pub fn get(&self, name: &str, line: i32) -> Result<Value, RuntimeError> {
+        match self.values.get(name) {
             Some(v) => {
                 // Challenge 8.2: don't allow use of uninitialized variable.
                 if let Some(v) = v {
                     Ok(v.clone())
                 } else {
-                    Err(RuntimeError::UninitializedVariable(
-                        token.lexeme.clone(),
-                        token.line,
-                    ))
+                    Err(RuntimeError::UninitializedVariable(name.to_string(), line))
                 }
             }
             None => {
                 if let Some(enclosing) = &self.enclosing {
-                    enclosing.borrow().get(token)
+                    enclosing.borrow().get(name, line)
                 } else {
-                    Err(RuntimeError::UndefinedVariable(
-                        token.lexeme.clone(),
-                        token.line,
-                    ))
+                    Err(RuntimeError::UndefinedVariable(name.to_string(), line))
                 }
             }
         }
     }
 
-    // TODO: Consider consuming token?
-    pub fn assign(&mut self, name: &Token, value: Option<Value>) -> Result<(), RuntimeError> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.clone(), value);
+    /// Assign to a variable on the given line.
+    pub fn assign(
+        &mut self,
+        name: &str,
+        line: i32,
+        value: Option<Value>,
+    ) -> Result<(), RuntimeError> {
+        if self.constants.contains(name) {
+            Err(RuntimeError::AssignToConstant(name.to_string(), line))
+        } else if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
             // * NOTE: No particular reasons for this. Can also do JS-style return value.
             Ok(())
         } else if let Some(enclosing) = &mut self.enclosing {
-            enclosing.borrow_mut().assign(name, value)
+            enclosing.borrow_mut().assign(name, line, value)
         } else {
-            Err(RuntimeError::UndefinedVariable(
-                name.lexeme.clone(),
-                name.line,
-            ))
+            Err(RuntimeError::UndefinedVariable(name.to_string(), line))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_slots_refuse_assignment() {
+        let outer = Rc::new(RefCell::new(Environment::new(None)));
+        outer
+            .borrow_mut()
+            .define_constant_slot(0, &Rc::from("k"), Value::Number(1.0));
+        outer.borrow_mut().define_slot(1, &Rc::from("v"), None);
+        let mut inner = Environment::new(Some(outer.clone()));
+
+        let result = inner.assign_at(1, 0, "k", 3, Some(Value::Number(99.0)));
+        assert!(
+            matches!(&result, Err(RuntimeError::AssignToConstant(name, 3)) if name == "k"),
+            "{result:?}"
+        );
+        let value = inner.get_at(1, 0, "k", 3).unwrap();
+        assert!(matches!(value, Value::Number(n) if n == 1.0), "{value:?}");
+
+        assert!(inner
+            .assign_at(1, 1, "v", 3, Some(Value::Number(2.0)))
+            .is_ok());
+        // Declaring a variable again in the same block replaces the constant.
+        outer
+            .borrow_mut()
+            .define_slot(0, &Rc::from("k"), Some(Value::Number(5.0)));
+        assert!(inner
+            .assign_at(1, 0, "k", 3, Some(Value::Number(6.0)))
+            .is_ok());
+    }
+}
@@ -1,6 +1,9 @@
 use std::error::Error;
 use std::fmt;
 
+use crate::token::Value;
+use crate::typecheck::Type;
+
 #[derive(Debug)]
 pub enum RuntimeError {
     /// Unary operator taking non-number operand
@@ -15,38 +18,150 @@ pub enum RuntimeError {
     UndefinedVariable(String, i32),
     /// Variable is not initialized
     UninitializedVariable(String, i32),
+    /// Assignment to a constant binding
+    AssignToConstant(String, i32),
+    /// Property access on a value without properties
+    OnlyModulesAndErrorsHaveProperties(i32),
+    /// Property is not defined by the module or error
+    UndefinedProperty(String, i32),
+    /// Assert statement condition is falsey: condition source and optional message
+    AssertionFailed(String, Option<String>, i32),
+    /// Imported file cannot be read: path and reason
+    ImportFailed(String, String, i32),
+    /// Module imports itself, directly or indirectly: chain of paths
+    ImportCycle(String, i32),
+    /// Call on a value that is not a function or method
+    NotCallable(i32),
+    /// Method is not defined for the receiver type
+    UndefinedMethod(String, Type, i32),
+    /// Function called with wrong number of arguments: name, expected, given
+    WrongArity(String, usize, usize, i32),
+    /// Function argument has the wrong type: name, position, expected type
+    WrongArgumentType(String, usize, Type, i32),
+    /// Function argument has the right type but an unusable value: name, position
+    ArgumentOutOfRange(String, usize, i32),
+    /// Indexing or slicing a value other than a string or list
+    NotIndexable(i32),
+    /// Index or slice bound is not an integer number
+    IndexNotInteger(i32),
+    /// Index or slice bound outside the sequence: index and length
+    IndexOutOfRange(f64, usize, i32),
+    /// File built-in called without filesystem capability
+    FsAccessDenied(i32),
+    /// Path resolves outside the allowed root directory
+    PathOutsideRoot(String, i32),
+    /// File operation failed: path and reason
+    IoFailed(String, String, i32),
+    /// Value raised by a throw statement
+    Thrown(Value, i32),
+    /// Unwinds to the innermost enclosing loop
+    Break(i32),
 }
 
-impl fmt::Display for RuntimeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl RuntimeError {
+    /// Source line where the error was raised.
+    pub fn line(&self) -> i32 {
+        match self {
+            Self::OperandNotNumber(line_number)
+            | Self::OperandsNotNumbers(line_number)
+            | Self::OperandsNotNumbersOrStrings(line_number)
+            | Self::DivideByZero(line_number)
+            | Self::UndefinedVariable(_, line_number)
+            | Self::UninitializedVariable(_, line_number)
+            | Self::AssignToConstant(_, line_number)
+            | Self::OnlyModulesAndErrorsHaveProperties(line_number)
+            | Self::UndefinedProperty(_, line_number)
+            | Self::AssertionFailed(_, _, line_number)
+            | Self::ImportFailed(_, _, line_number)
+            | Self::ImportCycle(_, line_number)
+            | Self::NotCallable(line_number)
+            | Self::UndefinedMethod(_, _, line_number)
+            | Self::WrongArity(_, _, _, line_number)
+            | Self::WrongArgumentType(_, _, _, line_number)
+            | Self::ArgumentOutOfRange(_, _, line_number)
+            | Self::NotIndexable(line_number)
+            | Self::IndexNotInteger(line_number)
+            | Self::IndexOutOfRange(_, _, line_number)
+            | Self::FsAccessDenied(line_number)
+            | Self::PathOutsideRoot(_, line_number)
+            | Self::IoFailed(_, _, line_number)
+            | Self::Thrown(_, line_number)
+            | Self::Break(line_number) => *line_number,
+        }
+    }
+
+    /// Error message without the line number.
+    pub fn message(&self) -> String {
         match self {
-            Self::OperandNotNumber(line_number) => {
-                write!(f, "Operand must be a number.\n[line {}]", line_number)
+            Self::OperandNotNumber(_) => "Operand must be a number.".to_string(),
+            Self::OperandsNotNumbers(_) => "Operands must be numbers.".to_string(),
+            Self::OperandsNotNumbersOrStrings(_) => {
+                "Operands must be two numbers or two strings.".to_string()
+            }
+            Self::DivideByZero(_) => "Division by zero".to_string(),
+            Self::UndefinedVariable(name, _) => format!("Undefined variable {name}."),
+            Self::UninitializedVariable(name, _) => {
+                format!("Variable {name} has not been initialized.")
+            }
+            Self::AssignToConstant(name, _) => format!("Cannot assign to constant {name}."),
+            Self::OnlyModulesAndErrorsHaveProperties(_) => {
+                "Only modules and errors have properties.".to_string()
+            }
+            Self::UndefinedProperty(name, _) => format!("Undefined property {name}."),
+            Self::AssertionFailed(source, None, _) => format!("Assertion failed: {source}"),
+            Self::AssertionFailed(source, Some(message), _) => {
+                format!("Assertion failed: {source}: {message}")
+            }
+            Self::ImportFailed(path, reason, _) => format!("Could not import {path}: {reason}."),
+            Self::ImportCycle(chain, _) => format!("Import cycle: {chain}."),
+            Self::NotCallable(_) => "Can only call functions and methods.".to_string(),
+            Self::UndefinedMethod(name, receiver, _) => {
+                format!("Undefined method {name} for {receiver}.")
+            }
+            Self::WrongArity(name, expected, given, _) => {
+                format!("{name} expects {expected} arguments but got {given}.")
             }
-            Self::OperandsNotNumbers(line_number) => {
-                write!(f, "Operands must be numbers.\n[line {}]", line_number)
+            Self::WrongArgumentType(name, position, expected, _) => {
+                format!("Argument {position} of {name} must be a {expected}.")
             }
-            Self::OperandsNotNumbersOrStrings(line_number) => {
-                write!(
-                    f,
-                    "Operands must be two numbers or two strings.\n[line {}]",
-                    line_number
-                )
+            Self::ArgumentOutOfRange(name, position, _) => {
+                format!("Argument {position} of {name} is out of range.")
             }
-            Self::DivideByZero(line_number) => {
-                write!(f, "Division by zero\n[line {}]", line_number)
+            Self::NotIndexable(_) => "Only strings and lists can be indexed.".to_string(),
+            Self::IndexNotInteger(_) => "Index must be an integer.".to_string(),
+            Self::IndexOutOfRange(index, length, _) => {
+                format!("Index {index} out of range for length {length}.")
             }
-            Self::UndefinedVariable(name, line_number) => {
-                write!(f, "Undefined variable {}.\n[line {}]", name, line_number)
+            Self::FsAccessDenied(_) => {
+                "Filesystem access is disabled; run with --allow-fs.".to_string()
             }
-            Self::UninitializedVariable(name, line_number) => {
-                write!(
-                    f,
-                    "Variable {name} has not been initialized.\n[line {line_number}]`"
-                )
+            Self::PathOutsideRoot(path, _) => {
+                format!("Path {path} is outside the allowed directory.")
             }
+            Self::IoFailed(path, reason, _) => format!("Could not access {path}: {reason}."),
+            Self::Thrown(value, _) => format!("Uncaught exception: {value}"),
+            Self::Break(_) => "Cannot break outside of a loop.".to_string(),
         }
     }
+
+    /// Whether a catch clause may handle this error; control flow is never caught.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self, Self::Break(_))
+    }
+
+    /// The value bound to the variable of a catch clause.
+    pub fn into_value(self) -> Value {
+        match self {
+            Self::Thrown(value, _) => value,
+            e => Value::Error(e.message(), e.line()),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n[line {}]", self.message(), self.line())
+    }
 }
 
 impl Error for RuntimeError {}
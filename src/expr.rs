@@ -1,6 +1,7 @@
 use crate::{
     error::RuntimeError,
     token::{Token, Value},
+    typecheck::Type,
 };
 
 pub trait ExprVisitor {
@@ -12,6 +13,40 @@ pub trait ExprVisitor {
     }
 }
 
+/// Where a variable lives, filled in by the resolver.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Binding {
+    /// Looked up by name from the current environment outwards: top-level variables,
+    /// built-ins and imported names.
+    #[default]
+    Global,
+    /// A block local: how many environments out, and its slot there.
+    Local { depth: usize, slot: usize },
+}
+
+impl Expr {
+    /// Line where the expression starts.
+    pub fn line(&self) -> i32 {
+        match self {
+            Expr::Assign { name, .. } => name.line,
+            Expr::Binary { left, .. } | Expr::Logical { left, .. } | Expr::Comma { left, .. } => {
+                left.line()
+            }
+            Expr::Call { callee, .. } => callee.line(),
+            Expr::Get { object, .. } | Expr::Index { object, .. } | Expr::Slice { object, .. } => {
+                object.line()
+            }
+            Expr::Grouping { expression } => expression.line(),
+            Expr::List { bracket, .. } => bracket.line,
+            Expr::Literal { line, .. } => *line,
+            Expr::Unary { operator, .. } => operator.line,
+            Expr::Variable { token, .. } => token.line,
+            Expr::TypeTest { value, .. } => value.line(),
+            Expr::Ternary { condition, .. } => condition.line(),
+        }
+    }
+}
+
 // TODO: add new() implementation? I don't like specifying Box again and again.
 // TODO: Does not need to be a box? The expression doesn't have to own the subexpressions, right?
 #[derive(Debug)]
@@ -19,34 +54,66 @@ pub enum Expr {
     Assign {
         name: Token,
         value: Box<Expr>,
+        binding: Binding,
     },
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
     Grouping {
         expression: Box<Expr>,
     },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    List {
+        bracket: Token,
+        elements: Vec<Expr>,
+    },
     Literal {
         value: Value,
+        /// Source line, for compiled code.
+        line: i32,
     },
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Slice {
+        object: Box<Expr>,
+        bracket: Token,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
     Unary {
         operator: Token,
         right: Box<Expr>,
     },
     Variable {
         token: Token,
+        binding: Binding,
     },
     Comma {
         left: Box<Expr>,
         right: Box<Expr>,
     },
+    TypeTest {
+        value: Box<Expr>,
+        type_name: Type,
+    },
     Ternary {
         condition: Box<Expr>,
         left: Box<Expr>,
@@ -1,10 +1,24 @@
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
 use crate::environment::Environment;
 use crate::error::RuntimeError;
-use crate::expr::{Expr, ExprVisitor};
-use crate::stmt::{Stmt, StmtVisitor};
-use crate::token::{Token, TokenType, Value};
+use crate::expr::{Binding, Expr, ExprVisitor};
+use crate::methods;
+use crate::natives::{self, Host};
+use crate::optimizer;
+use crate::parser::Parser;
+use crate::profiler::Profiler;
+use crate::resolver;
+use crate::scanner::Scanner;
+use crate::stmt::{BranchId, Stmt, StmtVisitor};
+use crate::token::{intern, Token, TokenType, Value};
+use crate::typecheck::Type;
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 // TODO: Better name and definition.
@@ -13,78 +27,418 @@ pub trait InterpreterLike {
     fn new() -> Self;
 
     fn interpret(&mut self, statements: &[Stmt]);
+
+    /// Tell which file the statements come from, e.g. to resolve relative imports.
+    fn set_source_path(&mut self, _path: &Path) {}
+
+    /// Rewrite parsed statements before `interpret`; by default they are kept as parsed.
+    fn optimize(&self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements
+    }
+
+    /// Called once the file or prompt session is over, e.g. to write reports.
+    fn finish(&mut self) {}
 }
 
 pub struct Interpreter {
+    /// Built-in functions and constants, enclosing the top level of every file.
+    globals: Rc<RefCell<Environment>>,
     // environment: Environment,
     environment: Rc<RefCell<Environment>>,
+    /// Files being executed, outermost first; imports resolve relative to the last one.
+    import_stack: Vec<PathBuf>,
+    /// Top-level environments of already imported modules, by canonical path.
+    modules: HashMap<PathBuf, Rc<RefCell<Environment>>>,
+    /// State of the built-in functions.
+    host: Host,
+    /// Execution counts and times, with `--profile`.
+    profiler: Option<Profiler>,
+    /// Executed lines and branches, with `--coverage`.
+    coverage: Option<Coverage>,
+    /// Called before every statement, with `--debug`.
+    debugger: Option<Box<dyn Debugger>>,
+    /// Number of statements being executed, the current one included.
+    depth: usize,
+    /// Line of the statement being executed in each file of the import stack, or at the
+    /// prompt; only kept up to date while debugging.
+    lines: Vec<i32>,
+    /// Scopes of the import statements waiting for their module to run, outermost first.
+    importers: Vec<Rc<RefCell<Environment>>>,
+}
+
+/// A file being executed, as seen by a debugger: the innermost one, or one waiting for a
+/// module it imports.
+pub struct Frame {
+    /// None for lines typed at the prompt.
+    pub path: Option<PathBuf>,
+    pub line: i32,
+    /// Innermost scope of the statement running in the file.
+    pub environment: Rc<RefCell<Environment>>,
 }
 
 // TODO: Return Value::Boolean?
 /// Lox definition of "truthy" value
-fn is_truthy(literal: Value) -> bool {
+pub fn is_truthy(literal: &Value) -> bool {
     match literal {
         Value::Null => false,
-        Value::Boolean(bool) => bool,
+        Value::Boolean(bool) => *bool,
         Value::String(_) => true,
         Value::Number(_) => true,
+        Value::Error(..) => true,
+        Value::List(_) => true,
+        Value::Native(_) => true,
+        Value::Module(..) => true,
     }
 }
 
 // TODO: Return Value::Boolean?
 /// Lox definition of "equal" value
-fn is_equal(a: Value, b: Value) -> bool {
+pub fn is_equal(a: Value, b: Value) -> bool {
     match (a, b) {
         (Value::Null, Value::Null) => true,
         (Value::Null, _) => false,
         (_, Value::Null) => false,
         (Value::Boolean(a), Value::Boolean(b)) => a == b,
-        (Value::String(a), Value::String(b)) => a == b,
+        // Interned strings are usually the same allocation.
+        (Value::String(a), Value::String(b)) => Rc::ptr_eq(&a, &b) || a == b,
         (Value::Number(a), Value::Number(b)) => a == b,
+        (Value::Error(a, a_line), Value::Error(b, b_line)) => a == b && a_line == b_line,
+        (Value::List(a), Value::List(b)) => {
+            Rc::ptr_eq(&a, &b) || {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(a, b)| is_equal(a.clone(), b.clone()))
+            }
+        }
+        (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(&a, &b),
+        (Value::Module(_, a), Value::Module(_, b)) => Rc::ptr_eq(&a, &b),
         _ => false,
     }
 }
 
-// TODO: Can I express that this expect an unary expression in the function signature?
+/// Order two numbers or two strings for comparison operators; None if unordered (NaN)
+fn compare(left: Value, right: Value, line: i32) -> Result<Option<Ordering>, RuntimeError> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => Ok(left.partial_cmp(&right)),
+        (Value::String(left), Value::String(right)) => Ok(Some(left.cmp(&right))),
+        _ => Err(RuntimeError::OperandsNotNumbersOrStrings(line)),
+    }
+}
+
 /// Check if an unary operator's operand is number
-fn check_number_operand(operator: &Token, operand: Value) -> Result<f64, RuntimeError> {
+fn check_number_operand(operand: Value, line: i32) -> Result<f64, RuntimeError> {
     match operand {
         Value::Number(v) => Ok(v),
-        _ => Err(RuntimeError::OperandNotNumber(operator.line)),
+        _ => Err(RuntimeError::OperandNotNumber(line)),
     }
 }
 
-// TODO: Can I express that this expect a binary expression in the function signature?
 /// Check if a binary operator's operands are numbers
-fn check_number_operands(
-    operator: &Token,
-    left: Value,
-    right: Value,
-) -> Result<(f64, f64), RuntimeError> {
+fn check_number_operands(left: Value, right: Value, line: i32) -> Result<(f64, f64), RuntimeError> {
     match (left, right) {
         (Value::Number(left), Value::Number(right)) => Ok((left, right)),
-        _ => Err(RuntimeError::OperandsNotNumbers(operator.line)),
+        _ => Err(RuntimeError::OperandsNotNumbers(line)),
+    }
+}
+
+/// Apply a unary operator to an evaluated operand; errors are reported on the given line.
+pub fn unary(operator: &TokenType, right: Value, line: i32) -> Result<Value, RuntimeError> {
+    match operator {
+        TokenType::Minus => check_number_operand(right, line).map(|v| Value::Number(-v)),
+        TokenType::Bang => Ok(Value::Boolean(!is_truthy(&right))),
+        TokenType::Typeof => Ok(Value::String(intern(&Type::of(&right).to_string()))),
+        _ => unreachable!(), // TODO: Can this be enforced by the type?
+    }
+}
+
+/// Apply a binary operator to evaluated operands; errors are reported on the given line.
+pub fn binary(
+    operator: &TokenType,
+    left: Value,
+    right: Value,
+    line: i32,
+) -> Result<Value, RuntimeError> {
+    match operator {
+        TokenType::Greater => {
+            compare(left, right, line).map(|v| Value::Boolean(v == Some(Ordering::Greater)))
+        }
+        TokenType::GreaterEqual => compare(left, right, line)
+            .map(|v| Value::Boolean(matches!(v, Some(Ordering::Greater | Ordering::Equal)))),
+        TokenType::Less => {
+            compare(left, right, line).map(|v| Value::Boolean(v == Some(Ordering::Less)))
+        }
+        TokenType::LessEqual => compare(left, right, line)
+            .map(|v| Value::Boolean(matches!(v, Some(Ordering::Less | Ordering::Equal)))),
+        TokenType::Minus => check_number_operands(left, right, line)
+            .map(|(left, right)| Value::Number(left - right)),
+        TokenType::Plus => match (left, right) {
+            (Value::Number(left), Value::Number(right)) => Ok(Value::Number(left + right)),
+            (Value::String(left), Value::String(right)) => {
+                Ok(Value::String(format!("{left}{right}").into()))
+            }
+            // Reason: Chapter 7 Challenge 2
+            (Value::String(left), Value::Number(right)) => {
+                Ok(Value::String(format!("{left}{right}").into()))
+            }
+            (Value::Number(left), Value::String(right)) => {
+                Ok(Value::String(format!("{left}{right}").into()))
+            }
+            _ => Err(RuntimeError::OperandsNotNumbersOrStrings(line)),
+        },
+        TokenType::Slash => {
+            let (left, right) = check_number_operands(left, right, line)?;
+            let v = left / right;
+            // Reason: Chapter 7 Challenge 3
+            if v.is_infinite() {
+                Err(RuntimeError::DivideByZero(line))
+            } else {
+                Ok(Value::Number(v))
+            }
+        }
+        TokenType::Star => check_number_operands(left, right, line)
+            .map(|(left, right)| Value::Number(left * right)),
+        TokenType::BangEqual => Ok(Value::Boolean(!is_equal(left, right))),
+        TokenType::EqualEqual => Ok(Value::Boolean(is_equal(left, right))),
+        _ => unreachable!(), // TODO: Can this be expressed by the type instead?
+    }
+}
+
+/// Look up a binding of a module value, as in `module.name`, or the `message` or `line` of
+/// an error.
+pub fn get_property(object: Value, name: &str, line: i32) -> Result<Value, RuntimeError> {
+    match object {
+        Value::Module(_, environment) => {
+            environment.borrow().get(name, line).map_err(|e| match e {
+                RuntimeError::UndefinedVariable(name, line) => {
+                    RuntimeError::UndefinedProperty(name, line)
+                }
+                e => e,
+            })
+        }
+        Value::Error(message, error_line) => match name {
+            "message" => Ok(Value::String(message.as_str().into())),
+            "line" => Ok(Value::Number(error_line.into())),
+            _ => Err(RuntimeError::UndefinedProperty(name.to_string(), line)),
+        },
+        _ => Err(RuntimeError::OnlyModulesAndErrorsHaveProperties(line)),
+    }
+}
+
+/// Find an imported file relative to the importing one (the last of the import stack),
+/// rejecting a module that is still being imported.
+pub fn resolve_import(
+    import_stack: &[PathBuf],
+    relative: &str,
+    line: i32,
+) -> Result<PathBuf, RuntimeError> {
+    let base = import_stack
+        .last()
+        .and_then(|path| path.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let path = base
+        .join(relative)
+        .canonicalize()
+        .map_err(|e| RuntimeError::ImportFailed(relative.to_string(), e.to_string(), line))?;
+
+    if let Some(start) = import_stack.iter().position(|v| *v == path) {
+        let chain: Vec<String> = import_stack[start..]
+            .iter()
+            .chain([&path])
+            .map(|v| v.display().to_string())
+            .collect();
+        return Err(RuntimeError::ImportCycle(chain.join(" -> "), line));
+    }
+    Ok(path)
+}
+
+/// Read and parse an imported file, to be passed through `InterpreterLike::optimize`. A file
+/// with syntax errors fails to import, naming the first one.
+pub fn parse_module(path: &Path, relative: &str, line: i32) -> Result<Vec<Stmt>, RuntimeError> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| RuntimeError::ImportFailed(relative.to_string(), e.to_string(), line))?;
+    let mut parser = Parser::new(Scanner::new(&source).scan_tokens());
+    let statements = parser.parse();
+    match parser.errors().first() {
+        Some(e) => {
+            let reason = format!("line {e}");
+            Err(RuntimeError::ImportFailed(
+                relative.to_string(),
+                reason.trim_end_matches('.').to_string(),
+                line,
+            ))
+        }
+        None => Ok(statements),
     }
 }
 
 impl Interpreter {
+    /// Create an interpreter whose built-ins use the given host state.
+    pub fn with_host(host: Host) -> Self {
+        let mut globals = Environment::new(None);
+        natives::define_builtins(&mut globals);
+        let globals = Rc::new(RefCell::new(globals));
+        Self {
+            // environment: Environment::new(None),
+            environment: Rc::new(RefCell::new(Environment::new(Some(globals.clone())))),
+            globals,
+            import_stack: Vec::new(),
+            modules: HashMap::new(),
+            host,
+            profiler: None,
+            coverage: None,
+            debugger: None,
+            depth: 0,
+            lines: vec![0],
+            importers: Vec::new(),
+        }
+    }
+
+    /// Count executions and time of every line and function, reported by `finish`:
+    /// printed, or as collapsed stacks to the given file.
+    pub fn enable_profiler(&mut self, output: Option<PathBuf>) {
+        self.profiler = Some(Profiler::new(output));
+    }
+
+    /// Count executed lines and branches of every file run, written as an lcov tracefile
+    /// by `finish`. Lines typed at the prompt are not counted.
+    pub fn enable_coverage(&mut self, output: PathBuf) {
+        self.coverage = Some(Coverage::new(output));
+    }
+
+    /// Let a debugger pause before every statement that has a line.
+    pub fn enable_debugger(&mut self, debugger: Box<dyn Debugger>) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Innermost scope of the statement being executed.
+    pub fn environment(&self) -> &Rc<RefCell<Environment>> {
+        &self.environment
+    }
+
+    /// Files being executed, innermost first, while debugging.
+    pub fn frames(&self) -> Vec<Frame> {
+        let offset = self.lines.len().saturating_sub(self.import_stack.len());
+        let environments = self.importers.iter().chain([&self.environment]);
+        let mut frames: Vec<Frame> = self
+            .lines
+            .iter()
+            .zip(environments)
+            .enumerate()
+            .map(|(index, (line, environment))| Frame {
+                path: index
+                    .checked_sub(offset)
+                    .map(|index| self.import_stack[index].clone()),
+                line: *line,
+                environment: environment.clone(),
+            })
+            .collect();
+        frames.reverse();
+        frames
+    }
+
+    /// Evaluate an expression given as source in a scope of the running program, e.g.
+    /// typed at a debugger; errors come back as their messages.
+    pub fn evaluate_source(
+        &mut self,
+        source: &str,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Value, String> {
+        let tokens = Scanner::new(source).scan_tokens();
+        let expr = Parser::new(tokens)
+            .parse_expression()
+            .map_err(|e| e.to_string())?;
+        let expr = resolver::resolve_in(expr, &environment);
+        let previous = mem::replace(&mut self.environment, environment);
+        let result = self.evaluate(&expr);
+        self.environment = previous;
+        result.map_err(|e| e.to_string())
+    }
+
     // TODO: Re-consider these "visitor" pattern; it becomes awkward.
     fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
         self.visit_expr(expr)
     }
 
     fn execute(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
-        self.visit_stmt(stmt)
+        if self.profiler.is_none() && self.coverage.is_none() && self.debugger.is_none() {
+            return self.visit_stmt(stmt);
+        }
+        // Blocks are only counted through the statements inside them.
+        let Some(line) = stmt.line() else {
+            return self.visit_stmt(stmt);
+        };
+        self.depth += 1;
+        if let Some(mut debugger) = self.debugger.take() {
+            if let Some(current) = self.lines.last_mut() {
+                *current = line;
+            }
+            debugger.before_statement(self, line, self.depth);
+            self.debugger = Some(debugger);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit_line(line);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_line(line);
+        }
+        let result = self.visit_stmt(stmt);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
+        self.depth -= 1;
+        result
+    }
+
+    /// Evaluate the condition of an if statement or loop, counting which way it went for
+    /// coverage. A loop's line, counted once on entering the loop, is counted again for
+    /// every later test.
+    fn test_condition(
+        &mut self,
+        id: BranchId,
+        condition: &Expr,
+        retest: bool,
+    ) -> Result<bool, RuntimeError> {
+        if retest {
+            if let Some(profiler) = &mut self.profiler {
+                profiler.count_again();
+            }
+        }
+        let value = is_truthy(&self.evaluate(condition)?);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit_branch(id, usize::from(!value));
+        }
+        Ok(value)
+    }
+
+    /// Execute a loop body, returning whether the loop should keep going.
+    fn execute_loop_body(&mut self, body: &Stmt) -> Result<bool, RuntimeError> {
+        match self.execute(body) {
+            Ok(_) => Ok(true),
+            Err(RuntimeError::Break(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
     fn execute_block(
         &mut self,
         statements: &[Stmt],
         environment: Environment,
+    ) -> Result<(), RuntimeError> {
+        self.execute_in(statements, Rc::new(RefCell::new(environment)))
+    }
+
+    /// Execute statements with a shared environment as the current scope.
+    fn execute_in(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
     ) -> Result<(), RuntimeError> {
         // * NOTE: My translation of the book's construct; Java has no lifetime.
         // TODO: I can use temporary ownership of environment by sub-environment!
-        let mut temp = Rc::new(RefCell::new(environment));
+        let mut temp = environment;
         mem::swap(&mut self.environment, &mut temp);
         for statement in statements {
             match self.execute(statement) {
@@ -98,22 +452,135 @@ impl Interpreter {
         mem::swap(&mut self.environment, &mut temp);
         Ok(())
     }
+
+    fn evaluate_arguments(&mut self, arguments: &[Expr]) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.evaluate(argument)?);
+        }
+        Ok(values)
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        match callee {
+            Value::Native(native) => {
+                methods::check_arity(&native.name, native.arity, &arguments, paren.line)?;
+                let Some(profiler) = &mut self.profiler else {
+                    return (native.function)(&mut self.host, &arguments, paren.line);
+                };
+                profiler.enter_function(native.name.clone());
+                let result = (native.function)(&mut self.host, &arguments, paren.line);
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                }
+                result
+            }
+            _ => Err(RuntimeError::NotCallable(paren.line)),
+        }
+    }
+
+    /// Load a module on first import, returning its top-level environment.
+    fn import_module(
+        &mut self,
+        keyword: &Token,
+        path: &Token,
+    ) -> Result<Rc<RefCell<Environment>>, RuntimeError> {
+        let relative = match &path.literal {
+            Value::String(v) => v,
+            _ => unreachable!(), // Parser only accepts string literal.
+        };
+        let path = resolve_import(&self.import_stack, relative, keyword.line)?;
+        if let Some(module) = self.modules.get(&path) {
+            return Ok(module.clone());
+        }
+
+        let statements = self.optimize(parse_module(&path, relative, keyword.line)?);
+        let module = Rc::new(RefCell::new(Environment::new(Some(self.globals.clone()))));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_file(&path);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.enter_file(&path, &statements);
+        }
+        self.import_stack.push(path.clone());
+        self.lines.push(0);
+        self.importers.push(self.environment.clone());
+        let result = self.execute_in(&statements, module.clone());
+        self.importers.pop();
+        self.lines.pop();
+        self.import_stack.pop();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.leave_file();
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.leave_file();
+        }
+        result?;
+
+        self.modules.insert(path, module.clone());
+        Ok(module)
+    }
 }
 
 impl InterpreterLike for Interpreter {
     fn new() -> Self {
-        Self {
-            // environment: Environment::new(None),
-            environment: Rc::new(RefCell::new(Environment::new(None))),
+        Self::with_host(Host::new())
+    }
+
+    fn optimize(&self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        // Coverage reports the program as written, dead branches included.
+        if self.coverage.is_some() {
+            return resolver::resolve(statements);
+        }
+        resolver::resolve(optimizer::optimize(statements))
+    }
+
+    fn set_source_path(&mut self, path: &Path) {
+        self.import_stack.clear();
+        self.import_stack
+            .push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_file(path);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(profiler) = &self.profiler {
+            if let Err(e) = profiler.finish() {
+                eprintln!("Could not write profile: {e}");
+            }
+        }
+        if let Some(coverage) = &self.coverage {
+            if let Err(e) = coverage.finish() {
+                eprintln!("Could not write coverage: {e}");
+            }
         }
     }
     fn interpret(&mut self, statements: &[Stmt]) {
+        let covered = match (&mut self.coverage, self.import_stack.first()) {
+            (Some(coverage), Some(path)) => {
+                coverage.enter_file(path, statements);
+                true
+            }
+            _ => false,
+        };
         for statement in statements {
             match self.execute(statement) {
                 Ok(_) => {}
-                Err(e) => eprintln!("{e}"),
+                Err(e) => match &mut self.debugger {
+                    Some(debugger) => debugger.error(&e.to_string()),
+                    None => eprintln!("{e}"),
+                },
             }
         }
+        if let (true, Some(coverage)) = (covered, &mut self.coverage) {
+            coverage.leave_file();
+        }
     }
 }
 
@@ -125,18 +592,41 @@ impl StmtVisitor for Interpreter {
             Stmt::Expression { expression } => self.evaluate(expression).map(|_| {}),
             Stmt::Print { expression } => {
                 let value = self.evaluate(expression)?;
-                println!("{value}");
+                match &mut self.debugger {
+                    Some(debugger) => debugger.print(&value.to_string()),
+                    None => println!("{value}"),
+                }
                 Ok(())
             }
-            Stmt::VarDecl { name, initializer } => {
+            Stmt::VarDecl {
+                name,
+                initializer,
+                slot,
+                ..
+            } => {
                 let value = if let Some(initializer) = initializer {
                     Some(self.evaluate(initializer)?)
                 } else {
                     None
                 };
-                self.environment
-                    .borrow_mut()
-                    .define(name.lexeme.clone(), value);
+                let mut environment = self.environment.borrow_mut();
+                match slot {
+                    Some(slot) => environment.define_slot(*slot, &name.lexeme, value),
+                    None => environment.define(name.lexeme.clone(), value),
+                }
+                Ok(())
+            }
+            Stmt::ConstDecl {
+                name,
+                initializer,
+                slot,
+            } => {
+                let value = self.evaluate(initializer)?;
+                let mut environment = self.environment.borrow_mut();
+                match slot {
+                    Some(slot) => environment.define_constant_slot(*slot, &name.lexeme, value),
+                    None => environment.define_constant(name.lexeme.clone(), value),
+                }
                 Ok(())
             }
             Stmt::Block { statements } => {
@@ -144,20 +634,137 @@ impl StmtVisitor for Interpreter {
                 Ok(())
             }
             Stmt::If {
+                id,
                 condition,
                 then_branch,
                 else_branch,
             } => {
-                if is_truthy(self.evaluate(condition)?) {
+                if self.test_condition(*id, condition, false)? {
                     self.execute(then_branch)?;
                 } else if let Some(else_branch) = else_branch {
                     self.execute(else_branch)?;
                 }
                 Ok(())
             }
-            Stmt::While { condition, body } => {
-                while is_truthy(self.evaluate(condition)?) {
-                    self.execute(body)?;
+            Stmt::While {
+                id,
+                condition,
+                body,
+            } => {
+                let mut retest = false;
+                while self.test_condition(*id, condition, retest)? {
+                    retest = true;
+                    if !self.execute_loop_body(body)? {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            Stmt::DoWhile {
+                id,
+                body,
+                condition,
+            } => {
+                let mut retest = false;
+                while self.execute_loop_body(body)?
+                    && self.test_condition(*id, condition, retest)?
+                {
+                    retest = true;
+                }
+                Ok(())
+            }
+            Stmt::Loop { body } => {
+                while self.execute_loop_body(body)? {}
+                Ok(())
+            }
+            Stmt::Break { keyword } => Err(RuntimeError::Break(keyword.line)),
+            Stmt::Import {
+                keyword,
+                path,
+                alias,
+            } => {
+                let module = self.import_module(keyword, path)?;
+                if let Some(alias) = alias {
+                    self.environment.borrow_mut().define(
+                        alias.lexeme.clone(),
+                        Some(Value::Module(alias.lexeme.clone(), module)),
+                    );
+                } else {
+                    module
+                        .borrow()
+                        .export_into(&mut self.environment.borrow_mut());
+                }
+                Ok(())
+            }
+            Stmt::Assert {
+                keyword,
+                condition,
+                source,
+                message,
+            } => {
+                if is_truthy(&self.evaluate(condition)?) {
+                    return Ok(());
+                }
+                let message = if let Some(message) = message {
+                    Some(self.evaluate(message)?.to_string())
+                } else {
+                    None
+                };
+                Err(RuntimeError::AssertionFailed(
+                    source.clone(),
+                    message,
+                    keyword.line,
+                ))
+            }
+            Stmt::Throw { keyword, value } => {
+                Err(RuntimeError::Thrown(self.evaluate(value)?, keyword.line))
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let result =
+                    self.execute_block(body, Environment::new(Some(self.environment.clone())));
+                let result = match (result, catch) {
+                    (Err(e), Some(catch)) if e.is_catchable() => {
+                        let mut environment = Environment::new(Some(self.environment.clone()));
+                        environment.define_slot(0, &catch.name.lexeme, Some(e.into_value()));
+                        self.execute_block(&catch.body, environment)
+                    }
+                    (result, _) => result,
+                };
+                // An error from the finally block replaces the pending one.
+                if let Some(finally) = finally {
+                    self.execute_block(finally, Environment::new(Some(self.environment.clone())))?;
+                }
+                result
+            }
+            Stmt::Match {
+                id,
+                subject,
+                arms,
+                default,
+            } => {
+                let subject = self.evaluate(subject)?;
+                for (index, arm) in arms.iter().enumerate() {
+                    for value in &arm.values {
+                        if is_equal(subject.clone(), self.evaluate(value)?) {
+                            if let Some(coverage) = &mut self.coverage {
+                                coverage.hit_branch(*id, index);
+                            }
+                            return self.execute_block(
+                                &arm.body,
+                                Environment::new(Some(self.environment.clone())),
+                            );
+                        }
+                    }
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.hit_branch(*id, arms.len());
+                }
+                if let Some(default) = default {
+                    self.execute_block(default, Environment::new(Some(self.environment.clone())))?;
                 }
                 Ok(())
             }
@@ -170,16 +777,11 @@ impl ExprVisitor for Interpreter {
 
     fn visit_expr(&mut self, expr: &Expr) -> Result<Self::Output, RuntimeError> {
         match expr {
-            Expr::Literal { value } => Ok(value.clone()), // TODO: Refactor to not clone.
+            Expr::Literal { value, .. } => Ok(value.clone()), // TODO: Refactor to not clone.
             Expr::Grouping { expression } => self.evaluate(expression),
             Expr::Unary { operator, right } => {
                 let right = self.evaluate(right)?;
-
-                match operator.token_type {
-                    TokenType::Minus => check_number_operand(operator, right).map(Value::Number),
-                    TokenType::Bang => Ok(Value::Boolean(is_truthy(right))),
-                    _ => unreachable!(), // TODO: Can this be enforced by the type?
-                }
+                unary(&operator.token_type, right, operator.line)
             }
             Expr::Binary {
                 left,
@@ -188,73 +790,38 @@ impl ExprVisitor for Interpreter {
             } => {
                 let left = self.evaluate(left)?;
                 let right = self.evaluate(right)?;
-
-                match operator.token_type {
-                    TokenType::Greater => check_number_operands(operator, left, right)
-                        .map(|(left, right)| Value::Boolean(left > right)),
-                    TokenType::GreaterEqual => check_number_operands(operator, left, right)
-                        .map(|(left, right)| Value::Boolean(left >= right)),
-                    TokenType::Less => check_number_operands(operator, left, right)
-                        .map(|(left, right)| Value::Boolean(left < right)),
-                    TokenType::LessEqual => check_number_operands(operator, left, right)
-                        .map(|(left, right)| Value::Boolean(left <= right)),
-                    TokenType::Minus => check_number_operands(operator, left, right)
-                        .map(|(left, right)| Value::Number(left - right)),
-                    TokenType::Plus => match (left, right) {
-                        (Value::Number(left), Value::Number(right)) => {
-                            Ok(Value::Number(left + right))
-                        }
-                        (Value::String(left), Value::String(right)) => {
-                            Ok(Value::String(left + &right))
-                        }
-                        // Reason: Chapter 7 Challenge 2
-                        (Value::String(left), Value::Number(right)) => {
-                            Ok(Value::String(left + &right.to_string()))
-                        }
-                        (Value::Number(left), Value::String(right)) => {
-                            Ok(Value::String(left.to_string() + &right))
-                        }
-                        _ => Err(RuntimeError::OperandsNotNumbersOrStrings(operator.line)),
-                    },
-                    TokenType::Slash => {
-                        match check_number_operands(operator, left, right)
-                            .map(|(left, right)| left / right)
-                        {
-                            Ok(v) => {
-                                // Reason: Chapter 7 Challenge 3
-                                if v.is_infinite() {
-                                    Err(RuntimeError::DivideByZero(operator.line))
-                                } else {
-                                    Ok(Value::Number(v))
-                                }
-                            }
-                            Err(e) => Err(e),
-                        }
-                    }
-                    TokenType::Star => check_number_operands(operator, left, right)
-                        .map(|(left, right)| Value::Number(left * right)),
-                    TokenType::BangEqual => Ok(Value::Boolean(!is_equal(left, right))),
-                    TokenType::EqualEqual => Ok(Value::Boolean(is_equal(left, right))),
-                    _ => unreachable!(), // TODO: Can this be expressed by the type instead?
-                }
+                binary(&operator.token_type, left, right, operator.line)
             }
             Expr::Logical {
                 left,
                 operator,
                 right,
             } => {
-                let left = self.evaluate(left)?;
+                let left = match self.evaluate(left) {
+                    Err(RuntimeError::UninitializedVariable(..))
+                        if operator.token_type == TokenType::QuestionQuestionEqual =>
+                    {
+                        Value::Null
+                    }
+                    left => left?,
+                };
 
                 // TODO: cleanup; also in Rust case just using Expr::Binary seems fine...
                 match operator.token_type {
                     TokenType::Or => {
-                        if is_truthy(left.clone()) {
+                        if is_truthy(&left) {
                             return Ok(left);
                         }
                         self.evaluate(right)
                     }
                     TokenType::And => {
-                        if !is_truthy(left.clone()) {
+                        if !is_truthy(&left) {
+                            return Ok(left);
+                        }
+                        self.evaluate(right)
+                    }
+                    TokenType::QuestionQuestion | TokenType::QuestionQuestionEqual => {
+                        if !matches!(left, Value::Null) {
                             return Ok(left);
                         }
                         self.evaluate(right)
@@ -271,18 +838,98 @@ impl ExprVisitor for Interpreter {
                 left,
                 right,
             } => {
-                if is_truthy(self.evaluate(condition)?) {
+                if is_truthy(&self.evaluate(condition)?) {
                     self.evaluate(left)
                 } else {
                     self.evaluate(right)
                 }
             }
-            Expr::Variable { token } => self.environment.borrow().get(token),
-            Expr::Assign { name, value } => {
+            Expr::Variable { token, binding } => match binding {
+                Binding::Global => self.environment.borrow().get(&token.lexeme, token.line),
+                Binding::Local { depth, slot } => {
+                    self.environment
+                        .borrow()
+                        .get_at(*depth, *slot, &token.lexeme, token.line)
+                }
+            },
+            Expr::TypeTest { value, type_name } => {
+                let value = self.evaluate(value)?;
+                Ok(Value::Boolean(
+                    *type_name == Type::Any || Type::of(&value) == *type_name,
+                ))
+            }
+            Expr::Get { object, name } => {
+                let object = self.evaluate(object)?;
+                get_property(object, &name.lexeme, name.line)
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                // Built-in methods are called directly rather than through a method value.
+                let callee = if let Expr::Get { object, name } = callee.as_ref() {
+                    let object = self.evaluate(object)?;
+                    if matches!(object, Value::String(_) | Value::List(_)) {
+                        let arguments = self.evaluate_arguments(arguments)?;
+                        return methods::call_method(&object, &name.lexeme, arguments, name.line);
+                    }
+                    get_property(object, &name.lexeme, name.line)?
+                } else {
+                    self.evaluate(callee)?
+                };
+                let arguments = self.evaluate_arguments(arguments)?;
+                self.call(callee, arguments, paren)
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                methods::index(object, index, bracket.line)
+            }
+            Expr::Slice {
+                object,
+                bracket,
+                start,
+                end,
+            } => {
+                let object = self.evaluate(object)?;
+                let start = match start {
+                    Some(start) => Some(self.evaluate(start)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(end) => Some(self.evaluate(end)?),
+                    None => None,
+                };
+                methods::slice(object, start, end, bracket.line)
+            }
+            Expr::List { elements, .. } => {
+                let values = self.evaluate_arguments(elements)?;
+                Ok(Value::List(Rc::new(RefCell::new(values))))
+            }
+            Expr::Assign {
+                name,
+                value,
+                binding,
+            } => {
                 let value = self.evaluate(value)?;
-                self.environment
-                    .borrow_mut()
-                    .assign(name, Some(value.clone()))?;
+                let mut environment = self.environment.borrow_mut();
+                match binding {
+                    Binding::Global => {
+                        environment.assign(&name.lexeme, name.line, Some(value.clone()))?
+                    }
+                    Binding::Local { depth, slot } => environment.assign_at(
+                        *depth,
+                        *slot,
+                        &name.lexeme,
+                        name.line,
+                        Some(value.clone()),
+                    )?,
+                }
                 // JS-esque return of assigned expression value.
                 Ok(value)
             }
@@ -326,15 +973,30 @@ impl StmtVisitor for AstPrinter {
         match stmt {
             Stmt::Expression { expression } => self.visit_expr(expression),
             Stmt::Print { expression } => Ok(format!("(print {})", self.visit_expr(expression)?)),
-            Stmt::VarDecl { name, initializer } => Ok(if let Some(initializer) = initializer {
-                format!(
-                    "(declare {} {})",
-                    name.lexeme,
-                    self.visit_expr(initializer)?
-                )
-            } else {
-                format!("(declare {})", name.lexeme)
-            }),
+            Stmt::VarDecl {
+                name,
+                type_annotation,
+                initializer,
+                ..
+            } => {
+                let name = if let Some(type_annotation) = type_annotation {
+                    format!("{}: {}", name.lexeme, type_annotation)
+                } else {
+                    name.lexeme.to_string()
+                };
+                Ok(if let Some(initializer) = initializer {
+                    format!("(declare {} {})", name, self.visit_expr(initializer)?)
+                } else {
+                    format!("(declare {})", name)
+                })
+            }
+            Stmt::ConstDecl {
+                name, initializer, ..
+            } => Ok(format!(
+                "(const {} {})",
+                name.lexeme,
+                self.visit_expr(initializer)?
+            )),
             Stmt::Block { statements } => {
                 let mut strings = Vec::new();
                 for stmt in statements {
@@ -346,6 +1008,7 @@ impl StmtVisitor for AstPrinter {
                 condition,
                 then_branch,
                 else_branch,
+                ..
             } => {
                 let condition = self.visit_expr(condition)?;
                 let then_branch = self.visit_stmt(then_branch)?;
@@ -359,11 +1022,92 @@ impl StmtVisitor for AstPrinter {
                     Ok(format!("(if {} then {})", condition, then_branch))
                 }
             }
-            Stmt::While { condition, body } => Ok(format!(
+            Stmt::While {
+                condition, body, ..
+            } => Ok(format!(
                 "(while {} {})",
                 self.visit_expr(condition)?,
                 self.visit_stmt(body)?
             )),
+            Stmt::DoWhile {
+                body, condition, ..
+            } => Ok(format!(
+                "(do {} while {})",
+                self.visit_stmt(body)?,
+                self.visit_expr(condition)?
+            )),
+            Stmt::Loop { body } => Ok(format!("(loop {})", self.visit_stmt(body)?)),
+            Stmt::Break { .. } => Ok("(break)".to_string()),
+            Stmt::Import { path, alias, .. } => Ok(if let Some(alias) = alias {
+                format!("(import {} as {})", path.lexeme, alias.lexeme)
+            } else {
+                format!("(import {})", path.lexeme)
+            }),
+            Stmt::Assert {
+                condition, message, ..
+            } => Ok(if let Some(message) = message {
+                format!(
+                    "(assert {} {})",
+                    self.visit_expr(condition)?,
+                    self.visit_expr(message)?
+                )
+            } else {
+                format!("(assert {})", self.visit_expr(condition)?)
+            }),
+            Stmt::Throw { value, .. } => Ok(format!("(throw {})", self.visit_expr(value)?)),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut strings = Vec::new();
+                for stmt in body {
+                    strings.push(self.visit_stmt(stmt)?);
+                }
+                let mut string = format!("(try {})", strings.join(" "));
+                if let Some(catch) = catch {
+                    let mut strings = Vec::new();
+                    for stmt in &catch.body {
+                        strings.push(self.visit_stmt(stmt)?);
+                    }
+                    string += &format!(" (catch {} {})", catch.name.lexeme, strings.join(" "));
+                }
+                if let Some(finally) = finally {
+                    let mut strings = Vec::new();
+                    for stmt in finally {
+                        strings.push(self.visit_stmt(stmt)?);
+                    }
+                    string += &format!(" (finally {})", strings.join(" "));
+                }
+                Ok(string)
+            }
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+                ..
+            } => {
+                let mut strings = vec![self.visit_expr(subject)?];
+                for arm in arms {
+                    let mut values = Vec::new();
+                    for value in &arm.values {
+                        values.push(self.visit_expr(value)?);
+                    }
+                    let mut body = Vec::new();
+                    for stmt in &arm.body {
+                        body.push(self.visit_stmt(stmt)?);
+                    }
+                    strings.push(format!("(case ({}) {})", values.join(" "), body.join(" ")));
+                }
+                if let Some(default) = default {
+                    let mut body = Vec::new();
+                    for stmt in default {
+                        body.push(self.visit_stmt(stmt)?);
+                    }
+                    strings.push(format!("(default {})", body.join(" ")));
+                }
+                Ok(format!("(match {})", strings.join(" ")))
+            }
         }
     }
 }
@@ -384,7 +1128,7 @@ impl ExprVisitor for AstPrinter {
                 right,
             } => self.parenthesize(&operator.lexeme, &[left, right]),
             Expr::Grouping { expression } => self.parenthesize("group", &[expression]),
-            Expr::Literal { value } => Ok(match value {
+            Expr::Literal { value, .. } => Ok(match value {
                 Value::Null => "nil".to_string(),
                 v => format!("{v}"),
             }),
@@ -395,8 +1139,45 @@ impl ExprVisitor for AstPrinter {
                 left,
                 right,
             } => self.parenthesize("?", &[condition, left, right]),
-            Expr::Variable { token: name } => Ok(format!("(var {})", name.lexeme)),
-            Expr::Assign { name, value } => {
+            Expr::Variable { token: name, .. } => Ok(format!("(var {})", name.lexeme)),
+            Expr::TypeTest { value, type_name } => {
+                Ok(format!("(is {} {})", self.visit_expr(value)?, type_name))
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut strings = vec![self.visit_expr(callee)?];
+                for argument in arguments {
+                    strings.push(self.visit_expr(argument)?);
+                }
+                Ok(format!("(call {})", strings.join(" ")))
+            }
+            Expr::Index { object, index, .. } => self.parenthesize("index", &[object, index]),
+            Expr::Slice {
+                object, start, end, ..
+            } => {
+                let mut strings = vec![self.visit_expr(object)?];
+                for bound in [start, end] {
+                    strings.push(match bound {
+                        Some(bound) => self.visit_expr(bound)?,
+                        None => "nil".to_string(),
+                    });
+                }
+                Ok(format!("(slice {})", strings.join(" ")))
+            }
+            Expr::List { elements, .. } => {
+                let mut strings = Vec::new();
+                for element in elements {
+                    strings.push(self.visit_expr(element)?);
+                }
+                Ok(format!("(list {})", strings.join(" ")))
+            }
+            Expr::Get { object, name } => Ok(format!(
+                "(get {} {})",
+                self.visit_expr(object)?,
+                name.lexeme
+            )),
+            Expr::Assign { name, value, .. } => {
                 self.parenthesize(&format!("assign {}", name.lexeme), &[value])
             }
         }
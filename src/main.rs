@@ -3,24 +3,65 @@ use std::fs;
 use std::io::stdout;
 use std::io::{stdin, Write};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+mod chunk;
+mod compiler;
+mod coverage;
+mod dap;
+mod debugger;
+mod disassembler;
 mod environment;
 mod error;
 mod expr;
 mod interpreter;
+mod json;
+mod methods;
+mod natives;
+mod optimizer;
 mod parser;
+mod profiler;
+mod random;
+mod resolver;
 mod scanner;
 mod stmt;
 mod token;
+mod typecheck;
+mod vm;
 
-use clap::Parser as ClapParser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser as ClapParser, Subcommand, ValueEnum};
+use debugger::TerminalDebugger;
+use disassembler::Disassembler;
 use interpreter::{AstPrinter, Interpreter, InterpreterLike};
+use natives::Host;
 use parser::Parser;
 use scanner::Scanner;
+use typecheck::TypeChecker;
+use vm::Vm;
+
+/// Engine that executes programs.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Backend {
+    /// Tree-walk interpreter.
+    Tree,
+    /// Bytecode compiler and stack-based virtual machine.
+    Vm,
+}
+
+/// Modes other than running a file or the prompt.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve the Debug Adapter Protocol on stdin and stdout, for editors.
+    Dap,
+}
 
 /// Simple Lox language interpreter.
 #[derive(ClapParser, Debug)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Lox source file.
     #[arg(value_name = "FILE")]
     file: Option<PathBuf>,
@@ -28,33 +69,149 @@ struct Cli {
     /// Print AST instead of interpreting.
     #[arg(short, long)]
     ast: bool,
+
+    /// Check type annotations instead of interpreting.
+    #[arg(long, conflicts_with = "ast")]
+    check: bool,
+
+    /// Print the compiled bytecode instead of interpreting.
+    #[arg(long, conflicts_with_all = ["ast", "check"])]
+    disassemble: bool,
+
+    /// Seed for the random number built-ins, for reproducible runs.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Allow file built-ins to access paths under DIR (default: current directory).
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "."
+    )]
+    allow_fs: Option<PathBuf>,
+
+    /// Execution backend.
+    #[arg(long, value_enum, default_value = "tree")]
+    backend: Backend,
+
+    /// Report how long scanning, parsing and executing took, on stderr.
+    #[arg(long)]
+    time: bool,
+
+    /// Profile executions and time per line and function: print a report, or write
+    /// collapsed stacks for flame graph tools to FILE.
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["ast", "check", "disassemble"]
+    )]
+    profile: Option<Option<PathBuf>>,
+
+    /// Write an lcov tracefile of the lines and branches run to OUT, and print a summary.
+    #[arg(
+        long,
+        value_name = "OUT",
+        conflicts_with_all = ["ast", "check", "disassemble"]
+    )]
+    coverage: Option<PathBuf>,
+
+    /// Run FILE under an interactive debugger, paused before the first statement.
+    #[arg(long, requires = "file", conflicts_with_all = ["ast", "check", "disassemble"])]
+    debug: bool,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
+    let host = configure_host(&cli)?;
+    if let Some(Command::Dap) = cli.command {
+        if cli.file.is_some() {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "dap runs the program given by the client's launch request, not FILE",
+                )
+                .exit();
+        }
+        dap::serve(host);
+        return Ok(());
+    }
+    let tree_only = cli.profile.is_some() || cli.coverage.is_some() || cli.debug;
+    if tree_only && matches!(cli.backend, Backend::Vm) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--profile, --coverage and --debug are only supported by the tree backend",
+            )
+            .exit();
+    }
 
-    match (cli.ast, cli.file) {
-        (true, Some(file)) => run_file(AstPrinter::new(), file),
-        (true, None) => run_prompt(AstPrinter::new()),
-        (false, Some(file)) => run_file(Interpreter::new(), file),
-        (false, None) => run_prompt(Interpreter::new()),
+    let (file, time) = (cli.file, cli.time);
+    match (cli.ast, cli.check, cli.disassemble, cli.backend) {
+        (true, ..) => run_with(AstPrinter::new(), file, time),
+        (_, true, ..) => run_with(TypeChecker::new(), file, time),
+        (_, _, true, _) => run_with(Disassembler::new(), file, time),
+        (.., Backend::Tree) => {
+            let mut interpreter = Interpreter::with_host(host);
+            if let Some(output) = cli.profile {
+                interpreter.enable_profiler(output);
+            }
+            if let Some(output) = cli.coverage {
+                interpreter.enable_coverage(output);
+            }
+            if cli.debug {
+                interpreter.enable_debugger(Box::new(TerminalDebugger::new()));
+            }
+            run_with(interpreter, file, time)
+        }
+        (.., Backend::Vm) => run_with(Vm::with_host(host), file, time),
     }?;
 
     Ok(())
 }
 
+/// Create the built-ins' state with settings from the command line
+fn configure_host(cli: &Cli) -> Result<Host, Box<dyn Error>> {
+    let mut host = Host::new();
+    if let Some(seed) = cli.seed {
+        host.set_seed(seed);
+    }
+    if let Some(root) = &cli.allow_fs {
+        host.allow_fs(root)?;
+    }
+    Ok(host)
+}
+
+/// Run a file if given, otherwise the interactive prompt
+fn run_with<T: InterpreterLike>(
+    interpreter: T,
+    file: Option<PathBuf>,
+    time: bool,
+) -> Result<(), Box<dyn Error>> {
+    match file {
+        Some(file) => run_file(interpreter, file, time),
+        None => run_prompt(interpreter, time),
+    }
+}
+
 /// Load and interpret a Lox source code file
 fn run_file<T: InterpreterLike, P: AsRef<Path>>(
     mut interpreter: T,
     path: P,
+    time: bool,
 ) -> Result<(), Box<dyn Error>> {
-    let string = fs::read_to_string(path)?;
-    run(&mut interpreter, &string);
+    let string = fs::read_to_string(&path)?;
+    interpreter.set_source_path(path.as_ref());
+    run(&mut interpreter, &string, time);
+    interpreter.finish();
     Ok(())
 }
 
 /// Run interactive prompt for the Lox interpreter
-fn run_prompt<T: InterpreterLike>(mut interpreter: T) -> Result<(), Box<dyn Error>> {
+fn run_prompt<T: InterpreterLike>(mut interpreter: T, time: bool) -> Result<(), Box<dyn Error>> {
     let mut line = String::new();
     loop {
         print!("lox> ");
@@ -64,17 +221,33 @@ fn run_prompt<T: InterpreterLike>(mut interpreter: T) -> Result<(), Box<dyn Erro
             Ok(_) => (),
             Err(e) => return Err(Box::new(e)),
         };
-        run(&mut interpreter, &line);
+        run(&mut interpreter, &line, time);
         line.clear();
     }
+    interpreter.finish();
     Ok(())
 }
 
 /// Token scanner loop for a single file or line (interactive)
-fn run<T: InterpreterLike>(interpreter: &mut T, source: &str) {
+fn run<T: InterpreterLike>(interpreter: &mut T, source: &str, time: bool) {
+    let start = Instant::now();
     let mut scanner = Scanner::new(source);
     let tokens = scanner.scan_tokens();
+    let scanned = Instant::now();
     let mut parser = Parser::new(tokens);
     let statements = parser.parse();
+    for e in parser.errors() {
+        eprintln!("{e}");
+    }
+    let statements = interpreter.optimize(statements);
+    let parsed = Instant::now();
     interpreter.interpret(&statements);
+    let executed = Instant::now();
+
+    if time {
+        // Parsing includes the backend's optimization passes.
+        eprintln!("scan:    {:>10.3?}", scanned - start);
+        eprintln!("parse:   {:>10.3?}", parsed - scanned);
+        eprintln!("execute: {:>10.3?}", executed - parsed);
+    }
 }
@@ -0,0 +1,288 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::RuntimeError;
+use crate::token::Value;
+use crate::typecheck::Type;
+
+/// Longest string, in bytes, that `repeat` builds; anything longer is taken for a mistake
+/// rather than exhausting memory.
+const MAX_REPEAT_LENGTH: usize = 1 << 28;
+
+/// Check the number of arguments given to a built-in function or method.
+pub fn check_arity(
+    name: &str,
+    expected: usize,
+    arguments: &[Value],
+    line: i32,
+) -> Result<(), RuntimeError> {
+    if arguments.len() == expected {
+        Ok(())
+    } else {
+        Err(RuntimeError::WrongArity(
+            name.to_string(),
+            expected,
+            arguments.len(),
+            line,
+        ))
+    }
+}
+
+/// Get the argument at a zero-based position as a string.
+pub fn string_argument<'a>(
+    name: &str,
+    arguments: &'a [Value],
+    position: usize,
+    line: i32,
+) -> Result<&'a str, RuntimeError> {
+    match &arguments[position] {
+        Value::String(v) => Ok(v),
+        _ => Err(RuntimeError::WrongArgumentType(
+            name.to_string(),
+            position + 1,
+            Type::String,
+            line,
+        )),
+    }
+}
+
+/// Get the argument at a zero-based position as a number.
+pub fn number_argument(
+    name: &str,
+    arguments: &[Value],
+    position: usize,
+    line: i32,
+) -> Result<f64, RuntimeError> {
+    match &arguments[position] {
+        Value::Number(v) => Ok(*v),
+        _ => Err(RuntimeError::WrongArgumentType(
+            name.to_string(),
+            position + 1,
+            Type::Number,
+            line,
+        )),
+    }
+}
+
+/// Get the argument at a zero-based position as a list.
+pub fn list_argument(
+    name: &str,
+    arguments: &[Value],
+    position: usize,
+    line: i32,
+) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    match &arguments[position] {
+        Value::List(v) => Ok(v.clone()),
+        _ => Err(RuntimeError::WrongArgumentType(
+            name.to_string(),
+            position + 1,
+            Type::List,
+            line,
+        )),
+    }
+}
+
+/// Convert an index into a position of a sequence; slice bounds may also point at the end.
+fn position(
+    index: &Value,
+    length: usize,
+    is_bound: bool,
+    line: i32,
+) -> Result<usize, RuntimeError> {
+    let index = match index {
+        Value::Number(v) if v.fract() == 0.0 => *v,
+        _ => return Err(RuntimeError::IndexNotInteger(line)),
+    };
+    let limit = if is_bound {
+        length
+    } else {
+        length.saturating_sub(1)
+    };
+    if index < 0.0 || index > limit as f64 || (!is_bound && length == 0) {
+        Err(RuntimeError::IndexOutOfRange(index, length, line))
+    } else {
+        Ok(index as usize)
+    }
+}
+
+/// Evaluate `object[index]`; strings are indexed by Unicode scalar values.
+pub fn index(object: Value, index: Value, line: i32) -> Result<Value, RuntimeError> {
+    match object {
+        Value::String(v) => {
+            let chars: Vec<char> = v.chars().collect();
+            let i = position(&index, chars.len(), false, line)?;
+            Ok(Value::String(chars[i].to_string().into()))
+        }
+        Value::List(values) => {
+            let values = values.borrow();
+            let i = position(&index, values.len(), false, line)?;
+            Ok(values[i].clone())
+        }
+        _ => Err(RuntimeError::NotIndexable(line)),
+    }
+}
+
+/// Evaluate `object[start:end]`, where missing bounds mean the start and end of the sequence.
+pub fn slice(
+    object: Value,
+    start: Option<Value>,
+    end: Option<Value>,
+    line: i32,
+) -> Result<Value, RuntimeError> {
+    let bounds = |length: usize| -> Result<(usize, usize), RuntimeError> {
+        let start = match &start {
+            Some(v) => position(v, length, true, line)?,
+            None => 0,
+        };
+        let end = match &end {
+            Some(v) => position(v, length, true, line)?,
+            None => length,
+        };
+        // Crossed bounds give an empty slice rather than an error.
+        Ok((start, end.max(start)))
+    };
+    match object {
+        Value::String(v) => {
+            let chars: Vec<char> = v.chars().collect();
+            let (start, end) = bounds(chars.len())?;
+            Ok(Value::String(
+                chars[start..end].iter().collect::<String>().into(),
+            ))
+        }
+        Value::List(values) => {
+            let values = values.borrow();
+            let (start, end) = bounds(values.len())?;
+            Ok(Value::List(Rc::new(RefCell::new(
+                values[start..end].to_vec(),
+            ))))
+        }
+        _ => Err(RuntimeError::NotIndexable(line)),
+    }
+}
+
+/// Call a built-in method on a string or list receiver.
+pub fn call_method(
+    receiver: &Value,
+    method: &str,
+    arguments: Vec<Value>,
+    line: i32,
+) -> Result<Value, RuntimeError> {
+    match receiver {
+        Value::String(v) => string_method(v, method, &arguments, line),
+        Value::List(values) => list_method(&values.borrow(), method, &arguments, line),
+        _ => Err(RuntimeError::UndefinedMethod(
+            method.to_string(),
+            Type::of(receiver),
+            line,
+        )),
+    }
+}
+
+fn string_method(
+    string: &str,
+    method: &str,
+    arguments: &[Value],
+    line: i32,
+) -> Result<Value, RuntimeError> {
+    let arity = |expected| check_arity(method, expected, arguments, line);
+    Ok(match method {
+        "len" => {
+            arity(0)?;
+            Value::Number(string.chars().count() as f64)
+        }
+        "upper" => {
+            arity(0)?;
+            Value::String(string.to_uppercase().into())
+        }
+        "lower" => {
+            arity(0)?;
+            Value::String(string.to_lowercase().into())
+        }
+        "trim" => {
+            arity(0)?;
+            Value::String(string.trim().into())
+        }
+        "split" => {
+            arity(1)?;
+            let separator = string_argument(method, arguments, 0, line)?;
+            // An empty separator splits into single characters.
+            let parts: Vec<Value> = if separator.is_empty() {
+                string
+                    .chars()
+                    .map(|c| Value::String(c.to_string().into()))
+                    .collect()
+            } else {
+                string
+                    .split(separator)
+                    .map(|v| Value::String(v.into()))
+                    .collect()
+            };
+            Value::List(Rc::new(RefCell::new(parts)))
+        }
+        "contains" => {
+            arity(1)?;
+            Value::Boolean(string.contains(string_argument(method, arguments, 0, line)?))
+        }
+        "starts_with" => {
+            arity(1)?;
+            Value::Boolean(string.starts_with(string_argument(method, arguments, 0, line)?))
+        }
+        "replace" => {
+            arity(2)?;
+            let from = string_argument(method, arguments, 0, line)?;
+            let to = string_argument(method, arguments, 1, line)?;
+            Value::String(string.replace(from, to).into())
+        }
+        "find" => {
+            arity(1)?;
+            let needle = string_argument(method, arguments, 0, line)?;
+            // Position in characters, consistent with indexing; nil when absent.
+            match string.find(needle) {
+                Some(byte) => Value::Number(string[..byte].chars().count() as f64),
+                None => Value::Null,
+            }
+        }
+        "repeat" => {
+            arity(1)?;
+            let count = number_argument(method, arguments, 0, line)?;
+            let length = string
+                .len()
+                .checked_mul(count as usize)
+                .filter(|v| *v <= MAX_REPEAT_LENGTH);
+            if count < 0.0 || count.fract() != 0.0 || length.is_none() {
+                return Err(RuntimeError::ArgumentOutOfRange(
+                    method.to_string(),
+                    1,
+                    line,
+                ));
+            }
+            Value::String(string.repeat(count as usize).into())
+        }
+        _ => {
+            return Err(RuntimeError::UndefinedMethod(
+                method.to_string(),
+                Type::String,
+                line,
+            ))
+        }
+    })
+}
+
+fn list_method(
+    values: &[Value],
+    method: &str,
+    arguments: &[Value],
+    line: i32,
+) -> Result<Value, RuntimeError> {
+    match method {
+        "len" => {
+            check_arity(method, 0, arguments, line)?;
+            Ok(Value::Number(values.len() as f64))
+        }
+        _ => Err(RuntimeError::UndefinedMethod(
+            method.to_string(),
+            Type::List,
+            line,
+        )),
+    }
+}
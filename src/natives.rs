@@ -0,0 +1,353 @@
+use std::f64::consts;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, stdin, ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::methods::{list_argument, number_argument, string_argument};
+use crate::random::Rng;
+use crate::token::Value;
+
+/// State the built-ins keep between calls, owned by whichever backend runs the program.
+pub struct Host {
+    /// Generator behind the random built-ins.
+    rng: Rng,
+    /// Directory the file built-ins are confined to; None denies filesystem access.
+    fs_root: Option<PathBuf>,
+    /// Whether `read_line` may read standard input.
+    stdin: bool,
+}
+
+impl Host {
+    pub fn new() -> Self {
+        Self {
+            rng: Rng::from_time(),
+            fs_root: None,
+            stdin: true,
+        }
+    }
+
+    /// Make the random built-ins reproducible: the same seed gives the same sequence.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Let the file built-ins access paths under a directory.
+    pub fn allow_fs(&mut self, root: &Path) -> io::Result<()> {
+        self.fs_root = Some(root.canonicalize()?);
+        Ok(())
+    }
+
+    pub fn fs_root(&self) -> Option<&Path> {
+        self.fs_root.as_deref()
+    }
+
+    /// Make `read_line` find no input, e.g. when stdin carries a debugger's protocol.
+    pub fn deny_stdin(&mut self) {
+        self.stdin = false;
+    }
+}
+
+/// Body of a native function: host state, checked-arity arguments and call line.
+pub type NativeFn = dyn Fn(&mut Host, &[Value], i32) -> Result<Value, RuntimeError>;
+
+/// Function implemented in Rust and callable from Lox.
+pub struct NativeFunction {
+    pub name: Rc<str>,
+    pub arity: usize,
+    pub function: Box<NativeFn>,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+fn define_native(environment: &mut Environment, name: &str, arity: usize, function: Box<NativeFn>) {
+    let name: Rc<str> = name.into();
+    environment.define_constant(
+        name.clone(),
+        Value::Native(Rc::new(NativeFunction {
+            name,
+            arity,
+            function,
+        })),
+    );
+}
+
+/// Install every built-in function and constant.
+pub fn define_builtins(environment: &mut Environment) {
+    define_math(environment);
+    define_random(environment);
+    define_io(environment);
+}
+
+type UnaryMath = fn(f64) -> f64;
+type BinaryMath = fn(f64, f64) -> f64;
+
+/// Install math functions and constants. NaN follows IEEE 754: it is unequal to everything,
+/// including itself, and any function given NaN (`min` and `max` too) returns NaN.
+fn define_math(environment: &mut Environment) {
+    let unary: [(&str, UnaryMath); 9] = [
+        ("sqrt", f64::sqrt),
+        ("floor", f64::floor),
+        ("ceil", f64::ceil),
+        ("round", f64::round),
+        ("abs", f64::abs),
+        ("sin", f64::sin),
+        ("cos", f64::cos),
+        ("log", f64::ln),
+        ("exp", f64::exp),
+    ];
+    for (name, f) in unary {
+        define_native(
+            environment,
+            name,
+            1,
+            Box::new(move |_, arguments, line| {
+                Ok(Value::Number(f(number_argument(name, arguments, 0, line)?)))
+            }),
+        );
+    }
+
+    let binary: [(&str, BinaryMath); 3] = [
+        ("min", |a, b| {
+            if a.is_nan() || b.is_nan() {
+                f64::NAN
+            } else {
+                a.min(b)
+            }
+        }),
+        ("max", |a, b| {
+            if a.is_nan() || b.is_nan() {
+                f64::NAN
+            } else {
+                a.max(b)
+            }
+        }),
+        ("pow", f64::powf),
+    ];
+    for (name, f) in binary {
+        define_native(
+            environment,
+            name,
+            2,
+            Box::new(move |_, arguments, line| {
+                let a = number_argument(name, arguments, 0, line)?;
+                let b = number_argument(name, arguments, 1, line)?;
+                Ok(Value::Number(f(a, b)))
+            }),
+        );
+    }
+
+    for (name, value) in [
+        ("PI", consts::PI),
+        ("E", consts::E),
+        ("INF", f64::INFINITY),
+        ("NAN", f64::NAN),
+    ] {
+        environment.define_constant(name.into(), Value::Number(value));
+    }
+}
+
+/// Install random number functions, drawing from the host's seeded generator.
+fn define_random(environment: &mut Environment) {
+    define_native(
+        environment,
+        "random",
+        0,
+        Box::new(|host, _, _| Ok(Value::Number(host.rng().next_f64()))),
+    );
+    define_native(
+        environment,
+        "random_int",
+        2,
+        Box::new(|host, arguments, line| {
+            let name = "random_int";
+            let lo = number_argument(name, arguments, 0, line)?;
+            let hi = number_argument(name, arguments, 1, line)?;
+            if lo.fract() != 0.0 || !lo.is_finite() {
+                return Err(RuntimeError::ArgumentOutOfRange(name.to_string(), 1, line));
+            }
+            if hi.fract() != 0.0 || !hi.is_finite() || hi < lo {
+                return Err(RuntimeError::ArgumentOutOfRange(name.to_string(), 2, line));
+            }
+            // Both bounds are inclusive. Beyond 2^53 numbers are no longer all integers, so
+            // such a range could not be drawn from evenly.
+            let span = Some(hi - lo)
+                .filter(|v| *v < 2f64.powi(53))
+                .and_then(|v| (v as u64).checked_add(1))
+                .ok_or_else(|| RuntimeError::ArgumentOutOfRange(name.to_string(), 2, line))?;
+            Ok(Value::Number(lo + host.rng().below(span) as f64))
+        }),
+    );
+    define_native(
+        environment,
+        "shuffle",
+        1,
+        Box::new(|host, arguments, line| {
+            let list = list_argument("shuffle", arguments, 0, line)?;
+            let mut values = list.borrow_mut();
+            // Fisher-Yates, in place.
+            for i in (1..values.len()).rev() {
+                let j = host.rng().below(i as u64 + 1) as usize;
+                values.swap(i, j);
+            }
+            Ok(Value::Null)
+        }),
+    );
+    define_native(
+        environment,
+        "choice",
+        1,
+        Box::new(|host, arguments, line| {
+            let list = list_argument("choice", arguments, 0, line)?;
+            let values = list.borrow();
+            if values.is_empty() {
+                return Err(RuntimeError::ArgumentOutOfRange(
+                    "choice".to_string(),
+                    1,
+                    line,
+                ));
+            }
+            let i = host.rng().below(values.len() as u64) as usize;
+            Ok(values[i].clone())
+        }),
+    );
+}
+
+/// Remove `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Links followed at most when resolving a path, as a guard against cycles.
+const MAX_LINKS: usize = 40;
+
+/// Where a path leads once symbolic links are followed, including a last component that is
+/// a link to a file that does not exist yet: creating the file would follow it. None if
+/// the links do not end.
+fn follow_links(path: PathBuf) -> Option<PathBuf> {
+    let mut path = path;
+    for _ in 0..MAX_LINKS {
+        // Follow symbolic links in the existing part of the path.
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            if let Ok(parent) = parent.canonicalize() {
+                path = parent.join(name);
+            }
+        }
+        if let Ok(resolved) = path.canonicalize() {
+            return Some(resolved);
+        }
+        match fs::read_link(&path) {
+            Ok(target) => path = normalize(&path.parent()?.join(target)),
+            // Nothing there yet.
+            Err(_) => return Some(path),
+        }
+    }
+    None
+}
+
+/// Resolve a script-supplied path against the allowed root, refusing anything outside it.
+fn confine(host: &Host, path: &str, line: i32) -> Result<PathBuf, RuntimeError> {
+    let root = host.fs_root().ok_or(RuntimeError::FsAccessDenied(line))?;
+    // Normalize lexically, as the target of a write may not exist yet.
+    match follow_links(normalize(&root.join(path))) {
+        Some(resolved) if resolved.starts_with(root) => Ok(resolved),
+        _ => Err(RuntimeError::PathOutsideRoot(path.to_string(), line)),
+    }
+}
+
+fn io_failed(path: &str, e: io::Error, line: i32) -> RuntimeError {
+    RuntimeError::IoFailed(path.to_string(), e.to_string(), line)
+}
+
+fn write_to(path: &Path, content: &str, append: bool) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    file.write_all(content.as_bytes())
+}
+
+/// Install file and standard input functions. File access needs `Host::allow_fs`.
+fn define_io(environment: &mut Environment) {
+    define_native(
+        environment,
+        "read_file",
+        1,
+        Box::new(|host, arguments, line| {
+            let path = string_argument("read_file", arguments, 0, line)?;
+            match fs::read_to_string(confine(host, path, line)?) {
+                Ok(content) => Ok(Value::String(content.into())),
+                // A missing file is an expected outcome rather than an error.
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(Value::Null),
+                Err(e) => Err(io_failed(path, e, line)),
+            }
+        }),
+    );
+    for (name, append) in [("write_file", false), ("append_file", true)] {
+        define_native(
+            environment,
+            name,
+            2,
+            Box::new(move |host, arguments, line| {
+                let path = string_argument(name, arguments, 0, line)?;
+                let content = string_argument(name, arguments, 1, line)?;
+                write_to(&confine(host, path, line)?, content, append)
+                    .map_err(|e| io_failed(path, e, line))?;
+                Ok(Value::Null)
+            }),
+        );
+    }
+    define_native(
+        environment,
+        "file_exists",
+        1,
+        Box::new(|host, arguments, line| {
+            let path = string_argument("file_exists", arguments, 0, line)?;
+            Ok(Value::Boolean(confine(host, path, line)?.is_file()))
+        }),
+    );
+    define_native(
+        environment,
+        "read_line",
+        0,
+        Box::new(|host, _, line| {
+            if !host.stdin {
+                return Ok(Value::Null);
+            }
+            let mut input = String::new();
+            match stdin().read_line(&mut input) {
+                // End of input.
+                Ok(0) => Ok(Value::Null),
+                Ok(_) => {
+                    let trimmed = input.trim_end_matches(['\n', '\r']).len();
+                    input.truncate(trimmed);
+                    Ok(Value::String(input.into()))
+                }
+                Err(e) => Err(io_failed("stdin", e, line)),
+            }
+        }),
+    );
+}
@@ -0,0 +1,313 @@
+use crate::expr::Expr;
+use crate::interpreter::{binary, is_truthy, unary};
+use crate::stmt::{CatchClause, MatchArm, Stmt};
+use crate::token::{TokenType, Value};
+
+/// Fold expressions on literals and drop code that can never run. Folding uses the
+/// interpreter's own operators; an operation that fails is left for runtime to report.
+pub fn optimize(statements: Vec<Stmt>) -> Vec<Stmt> {
+    statements.into_iter().filter_map(statement).collect()
+}
+
+/// Simplify a statement; None if it does nothing.
+fn statement(stmt: Stmt) -> Option<Stmt> {
+    Some(match stmt {
+        Stmt::Assert {
+            keyword,
+            condition,
+            source,
+            message,
+        } => Stmt::Assert {
+            keyword,
+            condition: expression(condition),
+            source,
+            message: message.map(expression),
+        },
+        Stmt::Block { statements } => Stmt::Block {
+            statements: optimize(statements),
+        },
+        Stmt::ConstDecl {
+            name,
+            initializer,
+            slot,
+        } => Stmt::ConstDecl {
+            name,
+            initializer: expression(initializer),
+            slot,
+        },
+        Stmt::DoWhile {
+            id,
+            body,
+            condition,
+        } => Stmt::DoWhile {
+            id,
+            body: boxed(*body),
+            condition: expression(condition),
+        },
+        Stmt::Expression { expression: e } => Stmt::Expression {
+            expression: expression(e),
+        },
+        Stmt::If {
+            id,
+            condition,
+            then_branch,
+            else_branch,
+        } => match expression(condition) {
+            Expr::Literal { value, .. } => {
+                // The chosen branch runs in the enclosing scope, as it would in the if.
+                if is_truthy(&value) {
+                    return statement(*then_branch);
+                } else {
+                    return else_branch.and_then(|v| statement(*v));
+                }
+            }
+            condition => Stmt::If {
+                id,
+                condition,
+                then_branch: boxed(*then_branch),
+                else_branch: else_branch.map(|v| boxed(*v)),
+            },
+        },
+        Stmt::Loop { body } => Stmt::Loop { body: boxed(*body) },
+        Stmt::Match {
+            id,
+            subject,
+            arms,
+            default,
+        } => Stmt::Match {
+            id,
+            subject: expression(subject),
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    values: arm.values.into_iter().map(expression).collect(),
+                    body: optimize(arm.body),
+                })
+                .collect(),
+            default: default.map(optimize),
+        },
+        Stmt::Print { expression: e } => Stmt::Print {
+            expression: expression(e),
+        },
+        Stmt::Throw { keyword, value } => Stmt::Throw {
+            keyword,
+            value: expression(value),
+        },
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => Stmt::Try {
+            body: optimize(body),
+            catch: catch.map(|catch| CatchClause {
+                name: catch.name,
+                body: optimize(catch.body),
+            }),
+            finally: finally.map(optimize),
+        },
+        Stmt::VarDecl {
+            name,
+            type_annotation,
+            initializer,
+            slot,
+        } => Stmt::VarDecl {
+            name,
+            type_annotation,
+            initializer: initializer.map(expression),
+            slot,
+        },
+        Stmt::While {
+            id,
+            condition,
+            body,
+        } => match expression(condition) {
+            Expr::Literal { value, .. } if !is_truthy(&value) => return None,
+            condition => Stmt::While {
+                id,
+                condition,
+                body: boxed(*body),
+            },
+        },
+        stmt @ (Stmt::Break { .. } | Stmt::Import { .. }) => stmt,
+    })
+}
+
+/// Simplify a statement nested in another, where an empty block stands for nothing.
+fn boxed(stmt: Stmt) -> Box<Stmt> {
+    Box::new(statement(stmt).unwrap_or(Stmt::Block {
+        statements: Vec::new(),
+    }))
+}
+
+fn boxed_expression(expr: Expr) -> Box<Expr> {
+    Box::new(expression(expr))
+}
+
+fn expression(expr: Expr) -> Expr {
+    match expr {
+        Expr::Grouping { expression: e } => match expression(*e) {
+            // Only literals lose their parentheses: `(s.len)()` is not a method call.
+            literal @ Expr::Literal { .. } => literal,
+            e => Expr::Grouping {
+                expression: Box::new(e),
+            },
+        },
+        Expr::Unary { operator, right } => match expression(*right) {
+            Expr::Literal { value, line } => {
+                match unary(&operator.token_type, value.clone(), operator.line) {
+                    Ok(value) => Expr::Literal {
+                        value,
+                        line: operator.line,
+                    },
+                    Err(_) => Expr::Unary {
+                        operator,
+                        right: Box::new(Expr::Literal { value, line }),
+                    },
+                }
+            }
+            right => Expr::Unary {
+                operator,
+                right: Box::new(right),
+            },
+        },
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => match (expression(*left), expression(*right)) {
+            (
+                Expr::Literal {
+                    value: left,
+                    line: left_line,
+                },
+                Expr::Literal {
+                    value: right,
+                    line: right_line,
+                },
+            ) => match binary(
+                &operator.token_type,
+                left.clone(),
+                right.clone(),
+                operator.line,
+            ) {
+                Ok(value) => Expr::Literal {
+                    value,
+                    line: operator.line,
+                },
+                // E.g. division by zero, still reported when the expression runs.
+                Err(_) => Expr::Binary {
+                    left: Box::new(Expr::Literal {
+                        value: left,
+                        line: left_line,
+                    }),
+                    operator,
+                    right: Box::new(Expr::Literal {
+                        value: right,
+                        line: right_line,
+                    }),
+                },
+            },
+            (left, right) => Expr::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+        },
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => match expression(*left) {
+            Expr::Literal { value, line } => {
+                let short_circuits = match operator.token_type {
+                    TokenType::Or => is_truthy(&value),
+                    TokenType::And => !is_truthy(&value),
+                    _ => !matches!(value, Value::Null),
+                };
+                if short_circuits {
+                    Expr::Literal { value, line }
+                } else {
+                    expression(*right)
+                }
+            }
+            left => Expr::Logical {
+                left: Box::new(left),
+                operator,
+                right: boxed_expression(*right),
+            },
+        },
+        Expr::Ternary {
+            condition,
+            left,
+            right,
+        } => match expression(*condition) {
+            Expr::Literal { value, .. } => {
+                if is_truthy(&value) {
+                    expression(*left)
+                } else {
+                    expression(*right)
+                }
+            }
+            condition => Expr::Ternary {
+                condition: Box::new(condition),
+                left: boxed_expression(*left),
+                right: boxed_expression(*right),
+            },
+        },
+        Expr::Assign {
+            name,
+            value,
+            binding,
+        } => Expr::Assign {
+            name,
+            value: boxed_expression(*value),
+            binding,
+        },
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => Expr::Call {
+            callee: boxed_expression(*callee),
+            paren,
+            arguments: arguments.into_iter().map(expression).collect(),
+        },
+        Expr::Get { object, name } => Expr::Get {
+            object: boxed_expression(*object),
+            name,
+        },
+        Expr::Index {
+            object,
+            bracket,
+            index,
+        } => Expr::Index {
+            object: boxed_expression(*object),
+            bracket,
+            index: boxed_expression(*index),
+        },
+        Expr::Slice {
+            object,
+            bracket,
+            start,
+            end,
+        } => Expr::Slice {
+            object: boxed_expression(*object),
+            bracket,
+            start: start.map(|v| boxed_expression(*v)),
+            end: end.map(|v| boxed_expression(*v)),
+        },
+        Expr::List { bracket, elements } => Expr::List {
+            bracket,
+            elements: elements.into_iter().map(expression).collect(),
+        },
+        Expr::Comma { left, right } => Expr::Comma {
+            left: boxed_expression(*left),
+            right: boxed_expression(*right),
+        },
+        Expr::TypeTest { value, type_name } => Expr::TypeTest {
+            value: boxed_expression(*value),
+            type_name,
+        },
+        expr @ (Expr::Literal { .. } | Expr::Variable { .. }) => expr,
+    }
+}
@@ -1,9 +1,12 @@
 use core::fmt;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::{
-    expr::Expr,
-    stmt::Stmt,
+    expr::{Binding, Expr},
+    stmt::{BranchId, CatchClause, MatchArm, Stmt},
     token::{Token, TokenType, Value},
+    typecheck::Type,
 };
 
 // TODO: Revise to something simpler.
@@ -27,6 +30,40 @@ enum ParseErrorType {
     ExpectLeftParenAfterFor,
     ExpectSemicolonAfterLoopCondition,
     ExpectRightParenAfterForClauses,
+    ExpectWhileAfterDoBody,
+    ExpectLeftParenAfterWhileInDo,
+    ExpectSemicolonAfterDoWhile,
+    ExpectSemicolonAfterBreak,
+    BreakOutsideLoop,
+    ExpectLeftParenAfterMatch,
+    ExpectRightParenAfterMatchSubject,
+    ExpectLeftBraceAfterMatch,
+    ExpectColonAfterCase,
+    ExpectColonAfterDefault,
+    ExpectCaseOrDefault,
+    DuplicateDefault,
+    ExpectRightBraceAfterMatch,
+    ExpectConstInitializer,
+    AssignToConstant,
+    ExpectSemicolonAfterThrow,
+    ExpectLeftBraceAfterTry,
+    ExpectLeftParenAfterCatch,
+    ExpectCatchVariable,
+    ExpectRightParenAfterCatchVariable,
+    ExpectLeftBraceAfterCatch,
+    ExpectLeftBraceAfterFinally,
+    ExpectCatchOrFinally,
+    ExpectPropertyName,
+    ExpectSemicolonAfterAssert,
+    ExpectImportPath,
+    ExpectModuleName,
+    ExpectSemicolonAfterImport,
+    ExpectRightParenAfterArguments,
+    ExpectRightBracketAfterIndex,
+    ExpectRightBracketAfterElements,
+    ExpectTypeName,
+    UnknownType,
+    ExpectEndOfExpression,
 }
 
 #[derive(Debug, Clone)]
@@ -74,6 +111,41 @@ impl fmt::Display for ParseErrorType {
                 ExpectLeftParenAfterFor => "Expect '(' after for.".to_string(),
                 ExpectSemicolonAfterLoopCondition => "Expect ';' after loop condition.".to_string(),
                 ExpectRightParenAfterForClauses => "Expect ')' after for clauses.".to_string(),
+                ExpectWhileAfterDoBody => "Expect 'while' after do body.".to_string(),
+                ExpectLeftParenAfterWhileInDo => "Expect '(' after while.".to_string(),
+                ExpectSemicolonAfterDoWhile => "Expect ';' after do-while condition.".to_string(),
+                ExpectSemicolonAfterBreak => "Expect ';' after break.".to_string(),
+                BreakOutsideLoop => "Must be inside a loop to use 'break'.".to_string(),
+                ExpectLeftParenAfterMatch => "Expect '(' after match.".to_string(),
+                ExpectRightParenAfterMatchSubject => "Expect ')' after match subject.".to_string(),
+                ExpectLeftBraceAfterMatch => "Expect '{' before match arms.".to_string(),
+                ExpectColonAfterCase => "Expect ':' after case values.".to_string(),
+                ExpectColonAfterDefault => "Expect ':' after default.".to_string(),
+                ExpectCaseOrDefault => "Expect 'case' or 'default'.".to_string(),
+                DuplicateDefault => "Match can only have one default arm.".to_string(),
+                ExpectRightBraceAfterMatch => "Expect '}' after match arms.".to_string(),
+                ExpectConstInitializer => "Constant must be initialized.".to_string(),
+                AssignToConstant => "Cannot assign to constant.".to_string(),
+                ExpectSemicolonAfterThrow => "Expect ';' after thrown value.".to_string(),
+                ExpectLeftBraceAfterTry => "Expect '{' after try.".to_string(),
+                ExpectLeftParenAfterCatch => "Expect '(' after catch.".to_string(),
+                ExpectCatchVariable => "Expect catch variable name.".to_string(),
+                ExpectRightParenAfterCatchVariable =>
+                    "Expect ')' after catch variable.".to_string(),
+                ExpectLeftBraceAfterCatch => "Expect '{' after catch clause.".to_string(),
+                ExpectLeftBraceAfterFinally => "Expect '{' after finally.".to_string(),
+                ExpectCatchOrFinally => "Expect 'catch' or 'finally' after try block.".to_string(),
+                ExpectPropertyName => "Expect property name after '.'.".to_string(),
+                ExpectSemicolonAfterAssert => "Expect ';' after assertion.".to_string(),
+                ExpectImportPath => "Expect module path string after import.".to_string(),
+                ExpectModuleName => "Expect module name after 'as'.".to_string(),
+                ExpectSemicolonAfterImport => "Expect ';' after import.".to_string(),
+                ExpectRightParenAfterArguments => "Expect ')' after arguments.".to_string(),
+                ExpectRightBracketAfterIndex => "Expect ']' after index.".to_string(),
+                ExpectRightBracketAfterElements => "Expect ']' after list elements.".to_string(),
+                ExpectTypeName => "Expect type name after ':'.".to_string(),
+                UnknownType => "Unknown type.".to_string(),
+                ExpectEndOfExpression => "Expect end of expression.".to_string(),
             }
         )
     }
@@ -82,11 +154,28 @@ impl fmt::Display for ParseErrorType {
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Number of loops enclosing the statement being parsed, for validating `break`.
+    loop_depth: usize,
+    /// Names declared in each lexical scope, mapped to whether they are constant.
+    scopes: Vec<HashMap<Rc<str>, bool>>,
+    /// Errors of the statements skipped so far, in source order.
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            loop_depth: 0,
+            scopes: vec![HashMap::new()],
+            errors: Vec::new(),
+        }
+    }
+
+    /// Errors found by `parse`; the statements they were in are left out of its result.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
     }
 
     pub fn parse(&mut self) -> Vec<Stmt> {
@@ -99,9 +188,23 @@ impl Parser {
         statements
     }
 
+    /// Parse tokens holding exactly one expression, e.g. typed at a debugger prompt.
+    pub fn parse_expression(&mut self) -> Result<Expr> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(ParseError {
+                parse_error_type: ParseErrorType::ExpectEndOfExpression,
+                token: self.peek().clone(),
+            });
+        }
+        Ok(expr)
+    }
+
     fn declaration(&mut self) -> Option<Stmt> {
         let statement = if self.match_token_type(&[TokenType::Var]) {
             self.var_declaration()
+        } else if self.match_token_type(&[TokenType::Const]) {
+            self.const_declaration()
         } else {
             self.statement()
         };
@@ -111,7 +214,7 @@ impl Parser {
             Err(e) => {
                 // Not at parse(), to allow block continue with invalid statements?
                 // For what purpose though?
-                eprintln!("{e}");
+                self.errors.push(e);
                 self.synchronize();
                 None
             }
@@ -123,6 +226,12 @@ impl Parser {
             .consume(TokenType::Identifier, ParseErrorType::ExpectVarName)?
             .clone();
 
+        let type_annotation = if self.match_token_type(&[TokenType::Colon]) {
+            Some(self.type_name()?)
+        } else {
+            None
+        };
+
         let initializer = if self.match_token_type(&[TokenType::Equal]) {
             Some(self.expression()?)
         } else {
@@ -133,7 +242,46 @@ impl Parser {
             TokenType::Semicolon,
             ParseErrorType::ExpectSemicolonAfterVarDeclaration,
         )?;
-        Ok(Stmt::VarDecl { name, initializer })
+        self.declare(&name, false);
+        Ok(Stmt::VarDecl {
+            name,
+            type_annotation,
+            initializer,
+            slot: None,
+        })
+    }
+
+    fn type_name(&mut self) -> Result<Type> {
+        // `nil` is a keyword rather than an identifier.
+        if !self.match_token_type(&[TokenType::Identifier, TokenType::Nil]) {
+            return Err(ParseError {
+                parse_error_type: ParseErrorType::ExpectTypeName,
+                token: self.peek().clone(),
+            });
+        }
+        let token = self.previous();
+        token.lexeme.parse().map_err(|_| ParseError {
+            parse_error_type: ParseErrorType::UnknownType,
+            token: token.clone(),
+        })
+    }
+
+    fn const_declaration(&mut self) -> Result<Stmt> {
+        let name = self
+            .consume(TokenType::Identifier, ParseErrorType::ExpectVarName)?
+            .clone();
+        self.consume(TokenType::Equal, ParseErrorType::ExpectConstInitializer)?;
+        let initializer = self.expression()?;
+        self.consume(
+            TokenType::Semicolon,
+            ParseErrorType::ExpectSemicolonAfterVarDeclaration,
+        )?;
+        self.declare(&name, true);
+        Ok(Stmt::ConstDecl {
+            name,
+            initializer,
+            slot: None,
+        })
     }
 
     fn while_statement(&mut self) -> Result<Stmt> {
@@ -146,22 +294,85 @@ impl Parser {
             TokenType::RightParen,
             ParseErrorType::ExpectRightParenAfterCondition,
         )?;
-        let body = Box::new(self.statement()?);
-        Ok(Stmt::While { condition, body })
+        let body = Box::new(self.loop_body()?);
+        Ok(Stmt::While {
+            id: BranchId::next(),
+            condition,
+            body,
+        })
+    }
+
+    fn do_while_statement(&mut self) -> Result<Stmt> {
+        use TokenType::*;
+        let body = Box::new(self.loop_body()?);
+        self.consume(While, ParseErrorType::ExpectWhileAfterDoBody)?;
+        self.consume(LeftParen, ParseErrorType::ExpectLeftParenAfterWhileInDo)?;
+        let condition = self.expression()?;
+        self.consume(RightParen, ParseErrorType::ExpectRightParenAfterCondition)?;
+        self.consume(Semicolon, ParseErrorType::ExpectSemicolonAfterDoWhile)?;
+        Ok(Stmt::DoWhile {
+            id: BranchId::next(),
+            body,
+            condition,
+        })
+    }
+
+    fn loop_statement(&mut self) -> Result<Stmt> {
+        let body = Box::new(self.loop_body()?);
+        Ok(Stmt::Loop { body })
+    }
+
+    fn break_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            return Err(ParseError {
+                parse_error_type: ParseErrorType::BreakOutsideLoop,
+                token: keyword,
+            });
+        }
+        self.consume(
+            TokenType::Semicolon,
+            ParseErrorType::ExpectSemicolonAfterBreak,
+        )?;
+        Ok(Stmt::Break { keyword })
+    }
+
+    /// Parse the body statement of any loop, in which `break` is allowed.
+    fn loop_body(&mut self) -> Result<Stmt> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
     }
 
     fn statement(&mut self) -> Result<Stmt> {
         use TokenType::*;
         if self.match_token_type(&[For]) {
-            self.for_statement()
+            self.scoped(Self::for_statement)
         } else if self.match_token_type(&[If]) {
             self.if_statement()
         } else if self.match_token_type(&[Print]) {
             self.print_statement()
         } else if self.match_token_type(&[While]) {
             self.while_statement()
+        } else if self.match_token_type(&[Do]) {
+            self.do_while_statement()
+        } else if self.match_token_type(&[Loop]) {
+            self.loop_statement()
+        } else if self.match_token_type(&[Break]) {
+            self.break_statement()
+        } else if self.match_token_type(&[Match]) {
+            self.match_statement()
+        } else if self.match_token_type(&[Import]) {
+            self.import_statement()
+        } else if self.match_token_type(&[Assert]) {
+            self.assert_statement()
+        } else if self.match_token_type(&[Throw]) {
+            self.throw_statement()
+        } else if self.match_token_type(&[Try]) {
+            self.try_statement()
         } else if self.match_token_type(&[LeftBrace]) {
-            self.block()
+            self.scoped(Self::block)
         } else {
             self.expression_statement()
         }
@@ -188,6 +399,7 @@ impl Parser {
         } else {
             Expr::Literal {
                 value: Value::Boolean(true),
+                line: self.peek().line,
             }
         };
         // NOTE: Rather than this, shouldn't I be able to match expression statement?
@@ -201,7 +413,7 @@ impl Parser {
             None
         };
         self.consume(RightParen, ParseErrorType::ExpectRightParenAfterForClauses)?;
-        let mut body = self.statement()?;
+        let mut body = self.loop_body()?;
 
         if let Some(increment) = increment {
             // Append increment to the body statement.
@@ -217,6 +429,7 @@ impl Parser {
 
         // Desugar as a while loop.
         body = Stmt::While {
+            id: BranchId::next(),
             condition,
             body: Box::new(body),
         };
@@ -230,7 +443,181 @@ impl Parser {
         Ok(body)
     }
 
+    fn match_statement(&mut self) -> Result<Stmt> {
+        use TokenType::*;
+        self.consume(LeftParen, ParseErrorType::ExpectLeftParenAfterMatch)?;
+        let subject = self.expression()?;
+        self.consume(
+            RightParen,
+            ParseErrorType::ExpectRightParenAfterMatchSubject,
+        )?;
+        self.consume(LeftBrace, ParseErrorType::ExpectLeftBraceAfterMatch)?;
+
+        let mut arms = Vec::new();
+        let mut default = None;
+        while !self.check(&RightBrace) && !self.is_at_end() {
+            if self.match_token_type(&[Case]) {
+                // Not expression(), as the comma separates case values here.
+                let mut values = vec![self.assignment()?];
+                while self.match_token_type(&[Comma]) {
+                    values.push(self.assignment()?);
+                }
+                self.consume(Colon, ParseErrorType::ExpectColonAfterCase)?;
+                let body = self.scoped(Self::match_arm_body);
+                arms.push(MatchArm { values, body });
+            } else if self.match_token_type(&[Default]) {
+                if default.is_some() {
+                    // Reported here, in order, but the arm is still parsed so the rest of
+                    // the match is read as usual.
+                    self.errors.push(ParseError {
+                        parse_error_type: ParseErrorType::DuplicateDefault,
+                        token: self.previous().clone(),
+                    });
+                }
+                self.consume(Colon, ParseErrorType::ExpectColonAfterDefault)?;
+                let body = self.scoped(Self::match_arm_body);
+                default.get_or_insert(body);
+            } else {
+                return Err(ParseError {
+                    parse_error_type: ParseErrorType::ExpectCaseOrDefault,
+                    token: self.peek().clone(),
+                });
+            }
+        }
+
+        self.consume(RightBrace, ParseErrorType::ExpectRightBraceAfterMatch)?;
+        Ok(Stmt::Match {
+            id: BranchId::next(),
+            subject,
+            arms,
+            default,
+        })
+    }
+
+    /// Statements of a match arm, up to the next arm or the end of the match.
+    fn match_arm_body(&mut self) -> Vec<Stmt> {
+        use TokenType::*;
+        let mut statements = Vec::new();
+        while !self.check(&Case)
+            && !self.check(&Default)
+            && !self.check(&RightBrace)
+            && !self.is_at_end()
+        {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        statements
+    }
+
+    fn import_statement(&mut self) -> Result<Stmt> {
+        use TokenType::*;
+        let keyword = self.previous().clone();
+        let path = self
+            .consume(String, ParseErrorType::ExpectImportPath)?
+            .clone();
+        let alias = if self.match_token_type(&[As]) {
+            let alias = self
+                .consume(Identifier, ParseErrorType::ExpectModuleName)?
+                .clone();
+            self.declare(&alias, false);
+            Some(alias)
+        } else {
+            None
+        };
+        self.consume(Semicolon, ParseErrorType::ExpectSemicolonAfterImport)?;
+        Ok(Stmt::Import {
+            keyword,
+            path,
+            alias,
+        })
+    }
+
+    fn assert_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        let start = self.current;
+        // Not expression(), as the comma separates the message here.
+        let condition = self.assignment()?;
+        let source = self.source_text(start, self.current);
+        let message = if self.match_token_type(&[TokenType::Comma]) {
+            Some(self.assignment()?)
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::Semicolon,
+            ParseErrorType::ExpectSemicolonAfterAssert,
+        )?;
+        Ok(Stmt::Assert {
+            keyword,
+            condition,
+            source,
+            message,
+        })
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(
+            TokenType::Semicolon,
+            ParseErrorType::ExpectSemicolonAfterThrow,
+        )?;
+        Ok(Stmt::Throw { keyword, value })
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt> {
+        use TokenType::*;
+        self.consume(LeftBrace, ParseErrorType::ExpectLeftBraceAfterTry)?;
+        let body = self.scoped(Self::block_statements)?;
+
+        let catch = if self.match_token_type(&[Catch]) {
+            self.consume(LeftParen, ParseErrorType::ExpectLeftParenAfterCatch)?;
+            let name = self
+                .consume(Identifier, ParseErrorType::ExpectCatchVariable)?
+                .clone();
+            self.consume(
+                RightParen,
+                ParseErrorType::ExpectRightParenAfterCatchVariable,
+            )?;
+            self.consume(LeftBrace, ParseErrorType::ExpectLeftBraceAfterCatch)?;
+            let body = self.scoped(|parser| {
+                parser.declare(&name, false);
+                parser.block_statements()
+            })?;
+            Some(CatchClause { name, body })
+        } else {
+            None
+        };
+
+        let finally = if self.match_token_type(&[Finally]) {
+            self.consume(LeftBrace, ParseErrorType::ExpectLeftBraceAfterFinally)?;
+            Some(self.scoped(Self::block_statements)?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(ParseError {
+                parse_error_type: ParseErrorType::ExpectCatchOrFinally,
+                token: self.peek().clone(),
+            });
+        }
+
+        Ok(Stmt::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+
     fn block(&mut self) -> Result<Stmt> {
+        let statements = self.block_statements()?;
+        Ok(Stmt::Block { statements })
+    }
+
+    /// Statements of a block whose opening brace has been consumed.
+    fn block_statements(&mut self) -> Result<Vec<Stmt>> {
         let mut statements = Vec::new();
 
         use TokenType::*;
@@ -241,7 +628,7 @@ impl Parser {
         }
 
         self.consume(RightBrace, ParseErrorType::ExpectRightBraceAfterBlock)?;
-        Ok(Stmt::Block { statements })
+        Ok(statements)
     }
 
     fn if_statement(&mut self) -> Result<Stmt> {
@@ -258,6 +645,7 @@ impl Parser {
         };
 
         Ok(Stmt::If {
+            id: BranchId::next(),
             condition: expr,
             then_branch: Box::new(then_branch),
             else_branch,
@@ -303,16 +691,40 @@ impl Parser {
     fn assignment(&mut self) -> Result<Expr> {
         let expr = self.ternary()?;
 
-        if self.match_token_type(&[TokenType::Equal]) {
+        if self.match_token_type(&[TokenType::Equal, TokenType::QuestionQuestionEqual]) {
             let equals = self.previous().clone();
             // Right associative
             let value = self.ternary()?;
 
             use Expr::*;
             match expr {
-                Variable { token: name } => Ok(Expr::Assign {
+                Variable { token: name, .. } if self.is_constant(&name.lexeme) => Err(ParseError {
+                    parse_error_type: ParseErrorType::AssignToConstant,
+                    token: name,
+                }),
+                // Desugar `a ??= b` to `a ?? (a = b)`, so a non-nil `a` is not reassigned.
+                // The operator stays `??=`, for which a declared but uninitialized `a`
+                // counts as nil.
+                Variable { token: name, .. }
+                    if equals.token_type == TokenType::QuestionQuestionEqual =>
+                {
+                    Ok(Expr::Logical {
+                        left: Box::new(Expr::Variable {
+                            token: name.clone(),
+                            binding: Binding::Global,
+                        }),
+                        operator: equals,
+                        right: Box::new(Expr::Assign {
+                            name,
+                            value: Box::new(value),
+                            binding: Binding::Global,
+                        }),
+                    })
+                }
+                Variable { token: name, .. } => Ok(Expr::Assign {
                     name,
                     value: Box::new(value),
+                    binding: Binding::Global,
                 }),
                 _ => Err(ParseError {
                     parse_error_type: ParseErrorType::InvalidAssignment,
@@ -325,7 +737,7 @@ impl Parser {
     }
 
     fn ternary(&mut self) -> Result<Expr> {
-        let mut expr = self.or()?;
+        let mut expr = self.coalesce()?;
 
         if self.match_token_type(&[TokenType::QuestionMark]) {
             let left = self.expression()?;
@@ -341,6 +753,22 @@ impl Parser {
         Ok(expr)
     }
 
+    fn coalesce(&mut self) -> Result<Expr> {
+        let mut expr = self.or()?;
+
+        while self.match_token_type(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous().clone();
+            let right = Box::new(self.or()?);
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right,
+            }
+        }
+
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Expr> {
         let mut expr = self.and()?;
 
@@ -394,13 +822,23 @@ impl Parser {
         let mut expr = self.term()?;
 
         use TokenType::*;
-        while self.match_token_type(&[Greater, GreaterEqual, Less, LessEqual]) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
+        loop {
+            if self.match_token_type(&[Greater, GreaterEqual, Less, LessEqual]) {
+                let operator = self.previous().clone();
+                let right = self.term()?;
+                expr = Expr::Binary {
+                    left: Box::new(expr),
+                    operator,
+                    right: Box::new(right),
+                }
+            } else if self.match_token_type(&[Is]) {
+                let type_name = self.type_name()?;
+                expr = Expr::TypeTest {
+                    value: Box::new(expr),
+                    type_name,
+                }
+            } else {
+                break;
             }
         }
 
@@ -442,15 +880,101 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Result<Expr> {
-        if self.match_token_type(&[TokenType::Bang, TokenType::Minus]) {
+        if self.match_token_type(&[TokenType::Bang, TokenType::Minus, TokenType::Typeof]) {
             let operator = self.previous().clone();
             let right = Box::new(self.unary()?);
             Ok(Expr::Unary { operator, right })
         } else {
-            self.primary()
+            self.call()
         }
     }
 
+    fn call(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+
+        use TokenType::*;
+        loop {
+            if self.match_token_type(&[LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token_type(&[Dot]) {
+                let name = self
+                    .consume(Identifier, ParseErrorType::ExpectPropertyName)?
+                    .clone();
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                }
+            } else if self.match_token_type(&[LeftBracket]) {
+                expr = self.finish_index(expr)?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr> {
+        let arguments = self.arguments(&TokenType::RightParen)?;
+        let paren = self
+            .consume(
+                TokenType::RightParen,
+                ParseErrorType::ExpectRightParenAfterArguments,
+            )?
+            .clone();
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    /// Parse either `[index]` or `[start:end]` with both bounds optional.
+    fn finish_index(&mut self, object: Expr) -> Result<Expr> {
+        use TokenType::*;
+        let start = if self.check(&Colon) {
+            None
+        } else {
+            Some(Box::new(self.assignment()?))
+        };
+        let is_slice = self.match_token_type(&[Colon]);
+        let end = if is_slice && !self.check(&RightBracket) {
+            Some(Box::new(self.assignment()?))
+        } else {
+            None
+        };
+        let bracket = self
+            .consume(RightBracket, ParseErrorType::ExpectRightBracketAfterIndex)?
+            .clone();
+        let object = Box::new(object);
+        Ok(match (is_slice, start) {
+            (false, Some(index)) => Expr::Index {
+                object,
+                bracket,
+                index,
+            },
+            (_, start) => Expr::Slice {
+                object,
+                bracket,
+                start,
+                end,
+            },
+        })
+    }
+
+    /// Comma separated expressions up to the closing token, which is not consumed.
+    fn arguments(&mut self, closing: &TokenType) -> Result<Vec<Expr>> {
+        let mut arguments = Vec::new();
+        if !self.check(closing) {
+            // Not expression(), as the comma separates arguments here.
+            arguments.push(self.assignment()?);
+            while self.match_token_type(&[TokenType::Comma]) {
+                arguments.push(self.assignment()?);
+            }
+        }
+        Ok(arguments)
+    }
+
     fn primary(&mut self) -> Result<Expr> {
         // TODO: Refactor so that it can use normal Rust match
         // TODO: I think this TokenType actually makes thing confusing...
@@ -459,21 +983,36 @@ impl Parser {
         if self.match_token_type(&[False]) {
             Ok(Expr::Literal {
                 value: Value::Boolean(false),
+                line: self.previous().line,
             })
         } else if self.match_token_type(&[True]) {
             Ok(Expr::Literal {
                 value: Value::Boolean(true),
+                line: self.previous().line,
             })
         } else if self.match_token_type(&[Nil]) {
-            Ok(Expr::Literal { value: Value::Null })
+            Ok(Expr::Literal {
+                value: Value::Null,
+                line: self.previous().line,
+            })
         } else if self.match_token_type(&[Number, String]) {
             Ok(Expr::Literal {
                 value: self.previous().literal.clone(),
+                line: self.previous().line,
             })
         } else if self.match_token_type(&[Identifier]) {
             Ok(Expr::Variable {
                 token: self.previous().clone(),
+                binding: Binding::Global,
             })
+        } else if self.match_token_type(&[LeftBracket]) {
+            let bracket = self.previous().clone();
+            let elements = self.arguments(&RightBracket)?;
+            self.consume(
+                RightBracket,
+                ParseErrorType::ExpectRightBracketAfterElements,
+            )?;
+            Ok(Expr::List { bracket, elements })
         } else if self.match_token_type(&[LeftParen]) {
             let expr = self.expression()?;
             // TODO: I don't like how this is written
@@ -510,6 +1049,47 @@ impl Parser {
         }
     }
 
+    /// Source text of a token range, with whatever separated two tokens (spaces, line
+    /// breaks or comments) written as a single space.
+    fn source_text(&self, start: usize, end: usize) -> String {
+        let mut text = String::new();
+        let mut previous_end = None;
+        for token in &self.tokens[start..end] {
+            if previous_end.is_some_and(|end| token.offset > end) {
+                text.push(' ');
+            }
+            text.push_str(&token.lexeme);
+            previous_end = Some(token.offset + token.lexeme.chars().count());
+        }
+        text
+    }
+
+    /// Run a parsing function inside a new lexical scope.
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    /// Record a declaration in the innermost scope.
+    fn declare(&mut self, name: &Token, constant: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), constant);
+        }
+    }
+
+    /// Whether the name resolves to a constant in the scopes visible so far.
+    /// Names not declared in this parse (e.g. earlier prompt lines) are checked at runtime.
+    fn is_constant(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .unwrap_or(false)
+    }
+
     /// Check if the current token matches one of the token types, consuming it if true.
     fn match_token_type(&mut self, token_types: &[TokenType]) -> bool {
         for token_type in token_types {
@@ -584,7 +1164,8 @@ impl Parser {
 
             use TokenType::*;
             match self.peek().token_type {
-                Class | Fun | Var | For | If | While | Print | Return => return,
+                Class | Fun | Var | Const | For | If | While | Do | Loop | Match | Print
+                | Import | Assert | Throw | Try | Break | Return => return,
                 _ => {}
             }
 
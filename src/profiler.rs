@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Name of a file in reports: relative to the current directory if it is inside it.
+pub fn display_name(path: &Path) -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Where time is spent: a statement line of a source file (by index), or a function.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Site {
+    Line(usize, i32),
+    Function(Rc<str>),
+}
+
+/// Executions and time of a site. Total time includes the statements and calls it ran;
+/// own time does not.
+#[derive(Debug, Default, Clone, Copy)]
+struct Stats {
+    count: u64,
+    total: Duration,
+    own: Duration,
+}
+
+struct Frame {
+    site: Site,
+    start: Instant,
+    /// Total time of the frames directly inside this one.
+    inner: Duration,
+    /// Executions to count for the site, more than one for a loop tested repeatedly.
+    count: u64,
+}
+
+/// Execution counts and times per source line and per function, for `--profile`.
+pub struct Profiler {
+    /// Collapsed-stack file to write instead of printing a report.
+    output: Option<PathBuf>,
+    /// Names of source files; sites refer to them by index.
+    files: Vec<String>,
+    /// Files being executed, the current one last.
+    file_stack: Vec<usize>,
+    stack: Vec<Frame>,
+    sites: HashMap<Site, Stats>,
+    /// Own time of every distinct stack of sites, for flame graphs.
+    stacks: HashMap<Vec<Site>, Duration>,
+}
+
+impl Profiler {
+    pub fn new(output: Option<PathBuf>) -> Self {
+        Self {
+            output,
+            files: vec!["<prompt>".to_string()],
+            file_stack: vec![0],
+            stack: Vec::new(),
+            sites: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    /// Attribute lines to a source file until the matching `leave_file`.
+    pub fn enter_file(&mut self, path: &Path) {
+        let name = display_name(path);
+        let index = match self.files.iter().position(|v| *v == name) {
+            Some(index) => index,
+            None => {
+                self.files.push(name);
+                self.files.len() - 1
+            }
+        };
+        self.file_stack.push(index);
+    }
+
+    pub fn leave_file(&mut self) {
+        self.file_stack.pop();
+    }
+
+    /// Start timing a statement on a line of the current file.
+    pub fn enter_line(&mut self, line: i32) {
+        let file = self.file_stack.last().copied().unwrap_or_default();
+        self.enter(Site::Line(file, line));
+    }
+
+    /// Start timing a call to a function.
+    pub fn enter_function(&mut self, name: Rc<str>) {
+        self.enter(Site::Function(name));
+    }
+
+    fn enter(&mut self, site: Site) {
+        self.stack.push(Frame {
+            site,
+            start: Instant::now(),
+            inner: Duration::ZERO,
+            count: 1,
+        });
+    }
+
+    /// Count the innermost statement once more without timing it apart, e.g. a loop's line
+    /// for every test of its condition after the first.
+    pub fn count_again(&mut self) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.count += 1;
+        }
+    }
+
+    /// Stop timing the innermost statement or call.
+    pub fn exit(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = frame.start.elapsed();
+        let own = elapsed.saturating_sub(frame.inner);
+        if let Some(parent) = self.stack.last_mut() {
+            parent.inner += elapsed;
+        }
+
+        let frame_count = frame.count;
+        let mut path: Vec<Site> = self.stack.iter().map(|v| v.site.clone()).collect();
+        // A site already running further out (e.g. a loop and its body on one line) has
+        // its total counted there.
+        let nested = path.contains(&frame.site);
+        path.push(frame.site);
+        *self.stacks.entry(path.clone()).or_default() += own;
+
+        let stats = self.sites.entry(path.pop().unwrap()).or_default();
+        stats.count += frame_count;
+        stats.own += own;
+        if !nested {
+            stats.total += elapsed;
+        }
+    }
+
+    fn name(&self, site: &Site) -> String {
+        match site {
+            Site::Line(file, line) => format!("{}:{line}", self.files[*file]),
+            Site::Function(name) => format!("{name}()"),
+        }
+    }
+
+    /// Lines and functions by decreasing total time.
+    pub fn report(&self) -> String {
+        let mut sites: Vec<(&Site, &Stats)> = self.sites.iter().collect();
+        sites.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(b.1.count.cmp(&a.1.count)));
+
+        let mut output = format!(
+            "{:<24} {:>10} {:>12} {:>12}\n",
+            "line", "count", "total", "own"
+        );
+        for (site, stats) in sites {
+            let _ = writeln!(
+                output,
+                "{:<24} {:>10} {:>12.3?} {:>12.3?}",
+                self.name(site),
+                stats.count,
+                stats.total,
+                stats.own
+            );
+        }
+        output
+    }
+
+    /// Stacks in the collapsed format of flame graph tools: sites separated by `;`, then
+    /// the own time in microseconds.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .filter(|(_, time)| time.as_micros() > 0)
+            .map(|(stack, time)| {
+                let names: Vec<String> = stack.iter().map(|v| self.name(v)).collect();
+                format!("{} {}", names.join(";"), time.as_micros())
+            })
+            .collect();
+        lines.sort();
+        lines.join("\n") + "\n"
+    }
+
+    /// Print the report, or write the collapsed stacks if a file was given.
+    pub fn finish(&self) -> io::Result<()> {
+        match &self.output {
+            Some(path) => fs::write(path, self.collapsed_stacks()),
+            None => {
+                eprint!("{}", self.report());
+                Ok(())
+            }
+        }
+    }
+}
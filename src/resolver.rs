@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::expr::{Binding, Expr};
+use crate::stmt::Stmt;
+
+/// Names declared in one block scope. Variables get the next free slot; names bound by
+/// an aliased import stay in the environment's name table and map to None.
+#[derive(Default)]
+struct Scope {
+    names: HashMap<Rc<str>, Option<usize>>,
+    slots: usize,
+}
+
+impl Scope {
+    fn declare(&mut self, name: &Rc<str>) -> usize {
+        if let Some(Some(slot)) = self.names.get(name) {
+            // Redeclaring in the same scope replaces the variable, as `Environment::define` does.
+            return *slot;
+        }
+        let slot = self.slots;
+        self.slots += 1;
+        self.names.insert(name.clone(), Some(slot));
+        slot
+    }
+}
+
+/// Give every variable declared in a block a slot in its environment, and point each use
+/// of it there. The top level, and names that are not declared in a block, are left to be
+/// looked up by name.
+pub fn resolve(mut statements: Vec<Stmt>) -> Vec<Stmt> {
+    Resolver { scopes: Vec::new() }.statements(&mut statements);
+    statements
+}
+
+/// Point the variables of an expression at the scopes of a running environment chain,
+/// e.g. to evaluate it where a debugger paused.
+pub fn resolve_in(mut expr: Expr, environment: &Rc<RefCell<Environment>>) -> Expr {
+    let mut scopes = Vec::new();
+    let mut next = Some(environment.clone());
+    while let Some(environment) = next {
+        let environment = environment.borrow();
+        let mut scope = Scope::default();
+        for (name, slot, _) in environment.variables() {
+            scope.names.insert(name, slot);
+        }
+        scopes.push(scope);
+        next = environment.enclosing();
+    }
+    // The top level and built-ins are looked up by name, as when resolving a file.
+    scopes.truncate(scopes.len().saturating_sub(2));
+    scopes.reverse();
+    Resolver { scopes }.expression(&mut expr);
+    expr
+}
+
+struct Resolver {
+    scopes: Vec<Scope>,
+}
+
+impl Resolver {
+    fn statements(&mut self, statements: &mut [Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Resolve statements that run in an environment of their own.
+    fn block(&mut self, statements: &mut [Stmt]) {
+        self.scopes.push(Scope::default());
+        self.statements(statements);
+        self.scopes.pop();
+    }
+
+    /// Declare a variable in the innermost scope, returning its slot if there is one.
+    fn declare(&mut self, name: &Rc<str>) -> Option<usize> {
+        self.scopes.last_mut().map(|scope| scope.declare(name))
+    }
+
+    fn lookup(&self, name: &str) -> Binding {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            match scope.names.get(name) {
+                Some(Some(slot)) => return Binding::Local { depth, slot: *slot },
+                Some(None) => return Binding::Global,
+                None => {}
+            }
+        }
+        Binding::Global
+    }
+
+    fn statement(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Assert {
+                condition, message, ..
+            } => {
+                self.expression(condition);
+                if let Some(message) = message {
+                    self.expression(message);
+                }
+            }
+            Stmt::Block { statements } => self.block(statements),
+            Stmt::ConstDecl {
+                name,
+                initializer,
+                slot,
+            } => {
+                // The initializer still sees an outer variable of the same name.
+                self.expression(initializer);
+                *slot = self.declare(&name.lexeme);
+            }
+            Stmt::VarDecl {
+                name,
+                initializer,
+                slot,
+                ..
+            } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                *slot = self.declare(&name.lexeme);
+            }
+            Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                self.statement(body);
+                self.expression(condition);
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.expression(expression)
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Import {
+                alias: Some(alias), ..
+            } => {
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.names.insert(alias.lexeme.clone(), None);
+                }
+            }
+            Stmt::Loop { body } => self.statement(body),
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+                ..
+            } => {
+                self.expression(subject);
+                for arm in arms {
+                    for value in &mut arm.values {
+                        self.expression(value);
+                    }
+                    self.block(&mut arm.body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+            Stmt::Throw { value, .. } => self.expression(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(body);
+                if let Some(catch) = catch {
+                    self.scopes.push(Scope::default());
+                    self.declare(&catch.name.lexeme);
+                    self.statements(&mut catch.body);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            // Unaliased imports bring in names unknown until the module runs; uses of them
+            // are not declared in any block, so they are looked up by name.
+            Stmt::Break { .. } | Stmt::Import { .. } => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Variable { token, binding } => *binding = self.lookup(&token.lexeme),
+            Expr::Assign {
+                name,
+                value,
+                binding,
+            } => {
+                self.expression(value);
+                *binding = self.lookup(&name.lexeme);
+            }
+            Expr::Binary { left, right, .. }
+            | Expr::Logical { left, right, .. }
+            | Expr::Comma { left, right } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.expression(object),
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expr::Slice {
+                object, start, end, ..
+            } => {
+                self.expression(object);
+                if let Some(start) = start {
+                    self.expression(start);
+                }
+                if let Some(end) = end {
+                    self.expression(end);
+                }
+            }
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::TypeTest { value, .. } => self.expression(value),
+            Expr::Ternary {
+                condition,
+                left,
+                right,
+            } => {
+                self.expression(condition);
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Literal { .. } => {}
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use crate::token::{Token, TokenType, Value};
+use crate::token::{intern, Token, TokenType, Value};
 use std::mem;
+use std::rc::Rc;
 use std::str::FromStr;
+use unicode_ident::{is_xid_continue, is_xid_start};
 
 /// Struct for the source and current state of the scanner
 /// TODO: I really want to use just standard iterator for this. perhaps later.
@@ -42,9 +44,10 @@ impl Scanner {
         }
         self.tokens.push(Token::new(
             TokenType::EoF,
-            "".to_string(),
+            Rc::from(""),
             Value::Null,
             self.line,
+            self.current,
         ));
         // TODO: Ugly
         mem::take(&mut self.tokens)
@@ -58,13 +61,25 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
-            '?' => self.add_token(TokenType::QuestionMark),
+            '?' => {
+                if self.match_char('?') {
+                    if self.match_char('=') {
+                        self.add_token(TokenType::QuestionQuestionEqual);
+                    } else {
+                        self.add_token(TokenType::QuestionQuestion);
+                    }
+                } else {
+                    self.add_token(TokenType::QuestionMark);
+                }
+            }
             ':' => self.add_token(TokenType::Colon),
             '!' => {
                 if self.match_char('=') {
@@ -104,22 +119,24 @@ impl Scanner {
                     self.add_token(TokenType::Slash);
                 }
             }
-            // TODO: instead match general whitespace characters
-            ' ' | '\r' | '\t' => {}
             '\n' => self.line += 1,
+            c if c.is_whitespace() => {}
             '"' => self.string(),
             c if c.is_ascii_digit() => self.number(),
-            // TODO: Later support full unicode as identifier??
-            c if c.is_ascii_alphanumeric() || c == '_' => self.identifier(),
+            c if is_xid_start(c) || c == '_' => self.identifier(),
             // TODO: Refactor.
-            _ => {
-                eprintln!("[line {}] Error: Unexpected character.", self.line);
+            c => {
+                eprintln!(
+                    "[line {}] Error: Unexpected character U+{:04X}.",
+                    self.line, c as u32
+                );
             }
         }
     }
 
     /// Consume a string of characters producing a string literal token
     fn string(&mut self) {
+        let start_line = self.line;
         // TODO: I should be able to consume and move at the same time with iterator?
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
@@ -128,6 +145,12 @@ impl Scanner {
             self.advance();
         }
 
+        // Report from where the literal started; drop the rest of the source as string content.
+        if self.is_at_end() {
+            eprintln!("[line {}] Error: Unterminated string.", start_line);
+            return;
+        }
+
         // The closing "
         self.advance();
 
@@ -137,7 +160,7 @@ impl Scanner {
             .iter()
             .collect();
         // TODO: I don't think I need these encapsulations
-        self.add_token_literal(TokenType::String, Value::String(value))
+        self.add_token_literal(TokenType::String, Value::String(intern(&value)))
     }
 
     /// Consume a string of characters producing a number literal token
@@ -174,8 +197,7 @@ impl Scanner {
 
     /// Consume a string of characters producing identifier or reserver keywords
     fn identifier(&mut self) {
-        // TODO: consider supporting full unicode
-        while self.peek().is_ascii_alphanumeric() {
+        while is_xid_continue(self.peek()) {
             self.advance();
         }
 
@@ -197,8 +219,20 @@ impl Scanner {
     fn add_token_literal(&mut self, token_type: TokenType, literal_value: Value) {
         // TODO: I think better integration with iterator type is possible
         let lexeme: String = self.source[self.start..self.current].iter().collect();
-        self.tokens
-            .push(Token::new(token_type, lexeme, literal_value, self.line))
+        // Names are looked up over and over, so equal ones share an allocation. Other
+        // lexemes are not kept around, as the interned set is never emptied.
+        let lexeme = if token_type == TokenType::Identifier {
+            intern(&lexeme)
+        } else {
+            Rc::from(lexeme)
+        };
+        self.tokens.push(Token::new(
+            token_type,
+            lexeme,
+            literal_value,
+            self.line,
+            self.start,
+        ))
     }
 
     /// Test whether the next character matches given one, conditionally advancing the iterator if so.
@@ -215,7 +249,6 @@ impl Scanner {
 
     /// Advance character iterator returning a string. TODO: Actually make into an iterator
     fn advance(&mut self) -> char {
-        // TODO: causes panic with unterminated string literal
         let char = self.source[self.current];
         self.current += 1;
         char
@@ -245,3 +278,59 @@ impl Scanner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pieces that steer the scanner into its trickier states: unterminated strings and
+    /// comments, numbers with trailing dots, non-ASCII characters and stray symbols.
+    const PIECES: &[&str] = &[
+        "\"", "/*", "*/", "//", "\n", ".", "1", "12.", ".5", "e", "_", "é", "变量", "🦀", "\u{0}",
+        "\u{200b}", "\r", "\t", "@", "#", "\\", "?", ":", "!=", "==", "<=", ">", "var", "print",
+        " ",
+    ];
+
+    /// A xorshift64 generator, so every run scans the same inputs.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn below(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    #[test]
+    fn scanner_never_panics_on_arbitrary_input() {
+        let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..5_000 {
+            let mut source = String::new();
+            for _ in 0..rng.below(40) {
+                if rng.below(4) == 0 {
+                    // Any scalar value, surrogates excluded by from_u32.
+                    source.extend(char::from_u32(rng.below(0x11_0000) as u32));
+                } else {
+                    source.push_str(PIECES[rng.below(PIECES.len() as u64) as usize]);
+                }
+            }
+            let tokens = Scanner::new(&source).scan_tokens();
+            let last = tokens.last().expect("the scanner always ends with EoF");
+            assert_eq!(last.token_type, TokenType::EoF, "{source:?}");
+        }
+    }
+    #[test]
+    fn only_names_and_string_literals_are_interned() {
+        let tokens = Scanner::new("count count 1234.5 \"text\" \"text\"").scan_tokens();
+        assert!(Rc::ptr_eq(&tokens[0].lexeme, &tokens[1].lexeme));
+        assert!(Rc::ptr_eq(&tokens[0].lexeme, &intern("count")));
+        match (&tokens[3].literal, &tokens[4].literal) {
+            (Value::String(a), Value::String(b)) => assert!(Rc::ptr_eq(a, b)),
+            other => panic!("{other:?}"),
+        }
+        assert!(!Rc::ptr_eq(&tokens[2].lexeme, &intern("1234.5")));
+        assert!(!Rc::ptr_eq(&tokens[3].lexeme, &tokens[4].lexeme));
+    }
+}
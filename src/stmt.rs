@@ -1,6 +1,9 @@
+use std::cell::Cell;
+
 use crate::error::RuntimeError;
 use crate::expr::Expr;
 use crate::token::Token;
+use crate::typecheck::Type;
 
 pub trait StmtVisitor {
     type Output;
@@ -13,26 +16,128 @@ pub trait StmtVisitor {
 
 #[derive(Debug)]
 pub enum Stmt {
+    Assert {
+        keyword: Token,
+        condition: Expr,
+        /// Source text of the condition, for reporting.
+        source: String,
+        message: Option<Expr>,
+    },
     Block {
         statements: Vec<Stmt>,
     },
+    Break {
+        keyword: Token,
+    },
+    ConstDecl {
+        name: Token,
+        initializer: Expr,
+        /// Slot in the block's environment; None at the top level.
+        slot: Option<usize>,
+    },
+    DoWhile {
+        id: BranchId,
+        body: Box<Stmt>,
+        condition: Expr,
+    },
     Expression {
         expression: Expr,
     },
     If {
+        id: BranchId,
         condition: Expr,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
     },
+    Import {
+        keyword: Token,
+        path: Token,
+        alias: Option<Token>,
+    },
+    Loop {
+        body: Box<Stmt>,
+    },
+    Match {
+        id: BranchId,
+        subject: Expr,
+        arms: Vec<MatchArm>,
+        default: Option<Vec<Stmt>>,
+    },
     Print {
         expression: Expr,
     },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    Try {
+        body: Vec<Stmt>,
+        catch: Option<CatchClause>,
+        finally: Option<Vec<Stmt>>,
+    },
     VarDecl {
         name: Token,
+        type_annotation: Option<Type>,
         initializer: Option<Expr>,
+        /// Slot in the block's environment; None at the top level.
+        slot: Option<usize>,
     },
     While {
+        id: BranchId,
         condition: Expr,
         body: Box<Stmt>,
     },
 }
+
+thread_local! {
+    static NEXT_BRANCH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Identity of a branching statement, for coverage: unique among every statement parsed
+/// by the thread, so it stays the same however the statement is moved or dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BranchId(usize);
+
+impl BranchId {
+    pub fn next() -> Self {
+        NEXT_BRANCH.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            BranchId(id)
+        })
+    }
+}
+
+impl Stmt {
+    /// Line a statement is attributed to when it runs, e.g. the condition of a loop;
+    /// None for blocks, `loop` and `try`, which only run the statements inside them.
+    pub fn line(&self) -> Option<i32> {
+        match self {
+            Stmt::Assert { keyword, .. }
+            | Stmt::Break { keyword }
+            | Stmt::Import { keyword, .. }
+            | Stmt::Throw { keyword, .. } => Some(keyword.line),
+            Stmt::ConstDecl { name, .. } | Stmt::VarDecl { name, .. } => Some(name.line),
+            Stmt::DoWhile { condition, .. }
+            | Stmt::If { condition, .. }
+            | Stmt::While { condition, .. } => Some(condition.line()),
+            Stmt::Expression { expression } | Stmt::Print { expression } => Some(expression.line()),
+            Stmt::Match { subject, .. } => Some(subject.line()),
+            Stmt::Block { .. } | Stmt::Loop { .. } | Stmt::Try { .. } => None,
+        }
+    }
+}
+
+/// A single `case` of a match statement.
+#[derive(Debug)]
+pub struct MatchArm {
+    pub values: Vec<Expr>,
+    pub body: Vec<Stmt>,
+}
+
+/// The `catch` clause of a try statement. The error is bound to slot 0 of its scope.
+#[derive(Debug)]
+pub struct CatchClause {
+    pub name: Token,
+    pub body: Vec<Stmt>,
+}
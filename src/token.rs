@@ -1,4 +1,10 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::natives::NativeFunction;
 
 /// Struct for the Lox tokens.
 // TODO: I don't like having all fields public...
@@ -7,22 +13,31 @@ pub struct Token {
     // TODO: Maybe better as a trait.
     /// Type of current token.
     pub token_type: TokenType,
-    /// Source string of the current token.
-    pub lexeme: String,
+    /// Source string of the current token, interned for identifiers.
+    pub lexeme: Rc<str>,
     // TODO: Part of type? Why is this even here?
     /// Holds dynamic value in the interpreter.
     pub literal: Value,
     /// Line number of the current token in the source code.
     pub line: i32,
+    /// Position of the token's first character in the source code, counted in characters.
+    pub offset: usize,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, literal: Value, line: i32) -> Self {
+    pub fn new(
+        token_type: TokenType,
+        lexeme: Rc<str>,
+        literal: Value,
+        line: i32,
+        offset: usize,
+    ) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
             line,
+            offset,
         }
     }
 }
@@ -43,13 +58,39 @@ impl Display for Token {
     }
 }
 
+thread_local! {
+    static INTERNED: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// Shared copy of a string, the same allocation for every equal string interned. Interned
+/// strings live as long as the thread, so only names, string literals and type names are.
+pub fn intern(string: &str) -> Rc<str> {
+    INTERNED.with_borrow_mut(|interned| {
+        if let Some(v) = interned.get(string) {
+            return v.clone();
+        }
+        let v: Rc<str> = Rc::from(string);
+        interned.insert(v.clone());
+        v
+    })
+}
+
 /// Values in Lox.
 #[derive(Debug, Clone)]
 pub enum Value {
-    String(String),
+    /// Immutable text; copies share it. Literals and names in source are interned.
+    String(Rc<str>),
     Number(f64),
     Boolean(bool),
     Null,
+    /// Runtime error caught by a catch clause: message and line number.
+    Error(String, i32),
+    /// Mutable sequence of values, shared between copies.
+    List(Rc<RefCell<Vec<Value>>>),
+    /// Built-in function.
+    Native(Rc<NativeFunction>),
+    /// Namespace of an imported module: name and top-level environment.
+    Module(Rc<str>, Rc<RefCell<Environment>>),
 }
 
 impl Display for Value {
@@ -60,6 +101,14 @@ impl Display for Value {
             String(v) => write!(f, "{v}"),
             Boolean(v) => write!(f, "{v}"),
             Null => write!(f, "null"),
+            Error(message, line) => write!(f, "{message} [line {line}]"),
+            List(values) => {
+                let values: Vec<std::string::String> =
+                    values.borrow().iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            Native(function) => write!(f, "{function:?}"),
+            Module(name, _) => write!(f, "<module {name}>"),
         }
     }
 }
@@ -73,6 +122,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -82,6 +133,9 @@ pub enum TokenType {
     Star,
     QuestionMark,
     Colon,
+    // Two or three character tokens.
+    QuestionQuestion,
+    QuestionQuestionEqual,
     // One or two character tokens.
     Bang,
     BangEqual,
@@ -97,19 +151,35 @@ pub enum TokenType {
     Number,
     // Keywords.
     And,
+    As,
+    Assert,
+    Break,
+    Case,
+    Catch,
     Class,
+    Const,
+    Default,
+    Do,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
+    Is,
+    Loop,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
+    Typeof,
     Var,
     While,
     EoF,
@@ -126,19 +196,35 @@ impl FromStr for TokenType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "and" => Ok(Self::And),
+            "as" => Ok(Self::As),
+            "assert" => Ok(Self::Assert),
+            "break" => Ok(Self::Break),
+            "case" => Ok(Self::Case),
+            "catch" => Ok(Self::Catch),
             "class" => Ok(Self::Class),
+            "const" => Ok(Self::Const),
+            "default" => Ok(Self::Default),
+            "do" => Ok(Self::Do),
             "else" => Ok(Self::Else),
             "false" => Ok(Self::False),
+            "finally" => Ok(Self::Finally),
             "for" => Ok(Self::For),
             "fun" => Ok(Self::Fun),
             "if" => Ok(Self::If),
+            "import" => Ok(Self::Import),
+            "is" => Ok(Self::Is),
+            "loop" => Ok(Self::Loop),
+            "match" => Ok(Self::Match),
             "nil" => Ok(Self::Nil),
             "or" => Ok(Self::Or),
             "print" => Ok(Self::Print),
             "return" => Ok(Self::Return),
             "super" => Ok(Self::Super),
             "this" => Ok(Self::This),
+            "throw" => Ok(Self::Throw),
             "true" => Ok(Self::True),
+            "try" => Ok(Self::Try),
+            "typeof" => Ok(Self::Typeof),
             "var" => Ok(Self::Var),
             "while" => Ok(Self::While),
             // TODO: Actually identifier; no need to follow the book to the letter!
@@ -0,0 +1,472 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::process;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::error::RuntimeError;
+use crate::expr::{Expr, ExprVisitor};
+use crate::interpreter::InterpreterLike;
+use crate::stmt::{Stmt, StmtVisitor};
+use crate::token::{Token, TokenType, Value};
+
+/// Static types of Lox values, as written in annotations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Number,
+    String,
+    Boolean,
+    Nil,
+    Error,
+    Function,
+    List,
+    Module,
+    /// Unannotated or otherwise unknown; checked at runtime only.
+    Any,
+}
+
+impl Type {
+    /// Runtime type of a value, as reported by `typeof`.
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Number(_) => Self::Number,
+            Value::String(_) => Self::String,
+            Value::Boolean(_) => Self::Boolean,
+            Value::Null => Self::Nil,
+            Value::Error(..) => Self::Error,
+            Value::List(_) => Self::List,
+            Value::Native(_) => Self::Function,
+            Value::Module(..) => Self::Module,
+        }
+    }
+
+    /// Whether a value of the other type may be stored where this type is expected.
+    fn accepts(self, other: Type) -> bool {
+        self == Self::Any || other == Self::Any || self == other
+    }
+
+    /// Common type of two alternatives, e.g. branches of a ternary.
+    fn join(self, other: Type) -> Self {
+        if self == other {
+            self
+        } else {
+            Self::Any
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Type::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                Number => "number",
+                String => "string",
+                Boolean => "boolean",
+                Nil => "nil",
+                Error => "error",
+                Function => "function",
+                List => "list",
+                Module => "module",
+                Any => "any",
+            }
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseTypeError;
+
+impl FromStr for Type {
+    type Err = ParseTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "number" => Ok(Self::Number),
+            "string" => Ok(Self::String),
+            "boolean" => Ok(Self::Boolean),
+            "nil" => Ok(Self::Nil),
+            "error" => Ok(Self::Error),
+            "function" => Ok(Self::Function),
+            "list" => Ok(Self::List),
+            "module" => Ok(Self::Module),
+            "any" => Ok(Self::Any),
+            _ => Err(ParseTypeError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TypeErrorType {
+    OperandNotNumber(Type),
+    OperandsNotNumbers(Type, Type),
+    OperandsNotNumbersOrStrings(Type, Type),
+    MismatchedInitializer(Type, Type),
+    MismatchedAssignment(Type, Type),
+}
+
+impl fmt::Display for TypeErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TypeErrorType::*;
+        match self {
+            OperandNotNumber(found) => write!(f, "Operand must be a number, found {found}."),
+            OperandsNotNumbers(left, right) => {
+                write!(f, "Operands must be numbers, found {left} and {right}.")
+            }
+            OperandsNotNumbersOrStrings(left, right) => write!(
+                f,
+                "Operands must be two numbers or two strings, found {left} and {right}."
+            ),
+            MismatchedInitializer(expected, found) => write!(
+                f,
+                "Cannot initialize variable of type {expected} with {found}."
+            ),
+            MismatchedAssignment(expected, found) => {
+                write!(f, "Cannot assign {found} to variable of type {expected}.")
+            }
+        }
+    }
+}
+
+/// Mismatch found before execution, reported at the offending token.
+#[derive(Debug, Clone)]
+pub struct TypeError {
+    type_error_type: TypeErrorType,
+    token: Token,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at '{}' {}",
+            self.token.line, self.token.lexeme, self.type_error_type
+        )
+    }
+}
+
+/// Checks annotated variables and operator operands without executing anything.
+/// Unannotated variables are `any`, so only known-wrong combinations are reported.
+pub struct TypeChecker {
+    /// Declared types of variables in each lexical scope.
+    scopes: Vec<HashMap<Rc<str>, Type>>,
+    errors: Vec<TypeError>,
+    /// Whether anything was reported, making `--check` exit with status 1.
+    failed: bool,
+}
+
+impl TypeChecker {
+    fn error(&mut self, type_error_type: TypeErrorType, token: &Token) {
+        self.errors.push(TypeError {
+            type_error_type,
+            token: token.clone(),
+        })
+    }
+
+    fn declare(&mut self, name: &Token, variable_type: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), variable_type);
+        }
+    }
+
+    fn lookup(&self, name: &Token) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&*name.lexeme))
+            .copied()
+            .unwrap_or(Type::Any)
+    }
+
+    /// Check statements inside a new lexical scope, declaring names first.
+    fn check_scoped(&mut self, names: &[&Token], statements: &[Stmt]) -> Result<(), RuntimeError> {
+        self.scopes.push(HashMap::new());
+        for name in names {
+            self.declare(name, Type::Any);
+        }
+        let mut result = Ok(());
+        for statement in statements {
+            result = self.visit_stmt(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.scopes.pop();
+        result
+    }
+
+    fn check_number_operands(&mut self, operator: &Token, left: Type, right: Type) {
+        if !Type::Number.accepts(left) || !Type::Number.accepts(right) {
+            self.error(TypeErrorType::OperandsNotNumbers(left, right), operator);
+        }
+    }
+}
+
+impl InterpreterLike for TypeChecker {
+    fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            errors: Vec::new(),
+            failed: false,
+        }
+    }
+
+    fn interpret(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            if let Err(e) = self.visit_stmt(statement) {
+                eprintln!("{e}");
+                self.failed = true;
+            }
+        }
+        for error in self.errors.drain(..) {
+            eprintln!("{error}");
+            self.failed = true;
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.failed {
+            process::exit(1);
+        }
+    }
+}
+
+impl StmtVisitor for TypeChecker {
+    type Output = ();
+
+    fn visit_stmt(&mut self, stmt: &Stmt) -> Result<Self::Output, RuntimeError> {
+        match stmt {
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.visit_expr(expression)?;
+            }
+            Stmt::VarDecl {
+                name,
+                type_annotation,
+                initializer,
+                ..
+            } => {
+                let declared = type_annotation.unwrap_or(Type::Any);
+                if let Some(initializer) = initializer {
+                    let found = self.visit_expr(initializer)?;
+                    if !declared.accepts(found) {
+                        self.error(TypeErrorType::MismatchedInitializer(declared, found), name);
+                    }
+                }
+                self.declare(name, declared);
+            }
+            Stmt::ConstDecl {
+                name, initializer, ..
+            } => {
+                // Never reassigned, so the initializer type holds.
+                let found = self.visit_expr(initializer)?;
+                self.declare(name, found);
+            }
+            Stmt::Block { statements } => self.check_scoped(&[], statements)?,
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.visit_expr(condition)?;
+                self.visit_stmt(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.visit_stmt(else_branch)?;
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            }
+            | Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                self.visit_expr(condition)?;
+                self.visit_stmt(body)?;
+            }
+            Stmt::Loop { body } => self.visit_stmt(body)?,
+            Stmt::Break { .. } => {}
+            Stmt::Match {
+                subject,
+                arms,
+                default,
+                ..
+            } => {
+                self.visit_expr(subject)?;
+                for arm in arms {
+                    for value in &arm.values {
+                        self.visit_expr(value)?;
+                    }
+                    self.check_scoped(&[], &arm.body)?;
+                }
+                if let Some(default) = default {
+                    self.check_scoped(&[], default)?;
+                }
+            }
+            Stmt::Throw { value, .. } => {
+                self.visit_expr(value)?;
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.check_scoped(&[], body)?;
+                if let Some(catch) = catch {
+                    self.check_scoped(&[&catch.name], &catch.body)?;
+                }
+                if let Some(finally) = finally {
+                    self.check_scoped(&[], finally)?;
+                }
+            }
+            Stmt::Assert {
+                condition, message, ..
+            } => {
+                self.visit_expr(condition)?;
+                if let Some(message) = message {
+                    self.visit_expr(message)?;
+                }
+            }
+            Stmt::Import { alias, .. } => {
+                if let Some(alias) = alias {
+                    self.declare(alias, Type::Any);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ExprVisitor for TypeChecker {
+    type Output = Type;
+
+    fn visit_expr(&mut self, expr: &Expr) -> Result<Self::Output, RuntimeError> {
+        Ok(match expr {
+            Expr::Literal { value, .. } => Type::of(value),
+            Expr::Grouping { expression } => self.visit_expr(expression)?,
+            Expr::Unary { operator, right } => {
+                let right = self.visit_expr(right)?;
+                match operator.token_type {
+                    TokenType::Minus => {
+                        if !Type::Number.accepts(right) {
+                            self.error(TypeErrorType::OperandNotNumber(right), operator);
+                        }
+                        Type::Number
+                    }
+                    TokenType::Typeof => Type::String,
+                    _ => Type::Boolean,
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.visit_expr(left)?;
+                let right = self.visit_expr(right)?;
+
+                use TokenType::*;
+                match operator.token_type {
+                    Greater | GreaterEqual | Less | LessEqual => {
+                        let comparable = Type::Number.accepts(left) && Type::Number.accepts(right)
+                            || Type::String.accepts(left) && Type::String.accepts(right);
+                        if !comparable {
+                            self.error(
+                                TypeErrorType::OperandsNotNumbersOrStrings(left, right),
+                                operator,
+                            );
+                        }
+                        Type::Boolean
+                    }
+                    Minus | Slash | Star => {
+                        self.check_number_operands(operator, left, right);
+                        Type::Number
+                    }
+                    // Mirrors the interpreter, including string and number concatenation.
+                    Plus => match (left, right) {
+                        (Type::Number, Type::Number) => Type::Number,
+                        (Type::String, Type::String | Type::Number)
+                        | (Type::Number, Type::String) => Type::String,
+                        (Type::Any, _) | (_, Type::Any) => Type::Any,
+                        _ => {
+                            self.error(
+                                TypeErrorType::OperandsNotNumbersOrStrings(left, right),
+                                operator,
+                            );
+                            Type::Any
+                        }
+                    },
+                    _ => Type::Boolean,
+                }
+            }
+            Expr::Logical { left, right, .. } => {
+                let left = self.visit_expr(left)?;
+                left.join(self.visit_expr(right)?)
+            }
+            Expr::Comma { left, right } => {
+                self.visit_expr(left)?;
+                self.visit_expr(right)?
+            }
+            Expr::Ternary {
+                condition,
+                left,
+                right,
+            } => {
+                self.visit_expr(condition)?;
+                let left = self.visit_expr(left)?;
+                left.join(self.visit_expr(right)?)
+            }
+            Expr::Variable { token, .. } => self.lookup(token),
+            Expr::Assign { name, value, .. } => {
+                let found = self.visit_expr(value)?;
+                let declared = self.lookup(name);
+                if !declared.accepts(found) {
+                    self.error(TypeErrorType::MismatchedAssignment(declared, found), name);
+                }
+                found
+            }
+            Expr::TypeTest { value, .. } => {
+                self.visit_expr(value)?;
+                Type::Boolean
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.visit_expr(callee)?;
+                for argument in arguments {
+                    self.visit_expr(argument)?;
+                }
+                Type::Any
+            }
+            Expr::Index { object, index, .. } => {
+                let object = self.visit_expr(object)?;
+                self.visit_expr(index)?;
+                if object == Type::String {
+                    Type::String
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Slice {
+                object, start, end, ..
+            } => {
+                let object = self.visit_expr(object)?;
+                for bound in [start, end].into_iter().flatten() {
+                    self.visit_expr(bound)?;
+                }
+                object
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.visit_expr(element)?;
+                }
+                Type::List
+            }
+            Expr::Get { object, .. } => {
+                self.visit_expr(object)?;
+                Type::Any
+            }
+        })
+    }
+}
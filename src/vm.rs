@@ -0,0 +1,478 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, OpCode, TYPES};
+use crate::compiler;
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::{self, InterpreterLike};
+use crate::methods;
+use crate::natives::{self, Host};
+use crate::optimizer;
+use crate::resolver;
+use crate::stmt::Stmt;
+use crate::token::{TokenType, Value};
+use crate::typecheck::Type;
+
+/// Stack-based virtual machine running compiled chunks; behaves like `Interpreter`.
+pub struct Vm {
+    /// Built-in functions and constants, enclosing the top level of every file.
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// Files being executed, outermost first; imports resolve relative to the last one.
+    import_stack: Vec<PathBuf>,
+    /// Top-level environments of already imported modules, by canonical path.
+    modules: HashMap<PathBuf, Rc<RefCell<Environment>>>,
+    /// State of the built-in functions.
+    host: Host,
+    stack: Vec<Value>,
+    /// Block locals of the running chunks, each chunk's after those of the one it imports
+    /// from; None until a declared variable is assigned.
+    locals: Vec<Option<Value>>,
+    /// Errors diverted to a handler, until `Catch` or `Rethrow` takes them.
+    errors: Vec<RuntimeError>,
+}
+
+/// Where execution resumes when an error is raised inside a try statement.
+struct Handler {
+    target: usize,
+    stack_len: usize,
+    locals_len: usize,
+    errors_len: usize,
+    environment: Rc<RefCell<Environment>>,
+}
+
+fn read_byte(chunk: &Chunk, ip: &mut usize) -> u8 {
+    *ip += 1;
+    chunk.code[*ip - 1]
+}
+
+fn read_u16(chunk: &Chunk, ip: &mut usize) -> u16 {
+    *ip += 2;
+    chunk.read_u16(*ip - 2)
+}
+
+fn read_i32(chunk: &Chunk, ip: &mut usize) -> i32 {
+    *ip += 4;
+    chunk.read_i32(*ip - 4)
+}
+
+impl Vm {
+    /// Create a virtual machine whose built-ins use the given host state.
+    pub fn with_host(host: Host) -> Self {
+        let mut globals = Environment::new(None);
+        natives::define_builtins(&mut globals);
+        let globals = Rc::new(RefCell::new(globals));
+        Self {
+            environment: Rc::new(RefCell::new(Environment::new(Some(globals.clone())))),
+            globals,
+            import_stack: Vec::new(),
+            modules: HashMap::new(),
+            host,
+            stack: Vec::new(),
+            locals: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("stack underflow")
+    }
+
+    /// Pop the top `count` values, oldest first.
+    fn pop_many(&mut self, count: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    /// Run a chunk in the current environment, which is restored if an error escapes.
+    fn run(&mut self, chunk: &Chunk) -> Result<(), RuntimeError> {
+        let stack_len = self.stack.len();
+        let base = self.locals.len();
+        let environment = self.environment.clone();
+        let mut handlers = Vec::new();
+        let mut ip = 0;
+        loop {
+            match self.execute(chunk, &mut ip, base, &mut handlers) {
+                Ok(()) => return Ok(()),
+                Err(e) => match handlers.pop() {
+                    Some(handler) => {
+                        self.stack.truncate(handler.stack_len);
+                        self.locals.truncate(handler.locals_len);
+                        self.errors.truncate(handler.errors_len);
+                        self.errors.push(e);
+                        self.environment = handler.environment;
+                        ip = handler.target;
+                    }
+                    None => {
+                        self.stack.truncate(stack_len);
+                        self.locals.truncate(base);
+                        self.environment = environment;
+                        return Err(e);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Define the block local at `slot`, which follows the defined ones or replaces one
+    /// declared again in the same block.
+    fn define_local(&mut self, slot: usize, value: Option<Value>) {
+        if slot >= self.locals.len() {
+            self.locals.resize(slot + 1, None);
+        }
+        self.locals[slot] = value;
+    }
+
+    /// Run instructions from `ip`; block locals of the chunk start at `base`.
+    fn execute(
+        &mut self,
+        chunk: &Chunk,
+        ip: &mut usize,
+        base: usize,
+        handlers: &mut Vec<Handler>,
+    ) -> Result<(), RuntimeError> {
+        while *ip < chunk.code.len() {
+            let line = chunk.lines[*ip];
+            let op = match OpCode::from_byte(read_byte(chunk, ip)) {
+                Some(op) => op,
+                None => unreachable!(), // Compiler only writes valid opcodes.
+            };
+            match op {
+                OpCode::Constant => {
+                    let index = read_u16(chunk, ip);
+                    self.stack.push(chunk.constants[index as usize].clone());
+                }
+                OpCode::Nil => self.stack.push(Value::Null),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::Dup => self.stack.push(self.peek().clone()),
+                OpCode::DefineVar => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let value = self.pop();
+                    self.environment
+                        .borrow_mut()
+                        .define(name.clone(), Some(value));
+                }
+                OpCode::DeclareVar => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    self.environment.borrow_mut().define(name.clone(), None);
+                }
+                OpCode::DefineConst => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let value = self.pop();
+                    self.environment
+                        .borrow_mut()
+                        .define_constant(name.clone(), value);
+                }
+                OpCode::GetVar => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let value = self.environment.borrow().get(name, line)?;
+                    self.stack.push(value);
+                }
+                OpCode::GetVarOrNil => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let value = match self.environment.borrow().get(name, line) {
+                        Err(RuntimeError::UninitializedVariable(..)) => Value::Null,
+                        value => value?,
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetVar => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let value = self.peek().clone();
+                    self.environment
+                        .borrow_mut()
+                        .assign(name, line, Some(value))?;
+                }
+                OpCode::DefineLocal => {
+                    // Skip the name, only kept for listings.
+                    *ip += 2;
+                    let slot = base + read_u16(chunk, ip) as usize;
+                    let value = self.pop();
+                    self.define_local(slot, Some(value));
+                }
+                OpCode::DeclareLocal => {
+                    *ip += 2;
+                    let slot = base + read_u16(chunk, ip) as usize;
+                    self.define_local(slot, None);
+                }
+                OpCode::GetLocal => {
+                    let name = read_u16(chunk, ip);
+                    let slot = base + read_u16(chunk, ip) as usize;
+                    match &self.locals[slot] {
+                        Some(value) => self.stack.push(value.clone()),
+                        // Challenge 8.2: don't allow use of uninitialized variable.
+                        None => {
+                            return Err(RuntimeError::UninitializedVariable(
+                                chunk.name(name).to_string(),
+                                line,
+                            ))
+                        }
+                    }
+                }
+                OpCode::GetLocalOrNil => {
+                    *ip += 2;
+                    let slot = base + read_u16(chunk, ip) as usize;
+                    let value = self.locals[slot].clone().unwrap_or(Value::Null);
+                    self.stack.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = base + read_u16(chunk, ip) as usize;
+                    self.locals[slot] = Some(self.peek().clone());
+                }
+                OpCode::PopLocals => {
+                    let slot = base + read_u16(chunk, ip) as usize;
+                    self.locals.truncate(slot);
+                }
+                OpCode::PushScope => {
+                    let enclosing = self.environment.clone();
+                    self.environment = Rc::new(RefCell::new(Environment::new(Some(enclosing))));
+                }
+                OpCode::PopScope => {
+                    let enclosing = self.environment.borrow().enclosing();
+                    self.environment = enclosing.expect("scope underflow");
+                }
+                OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide => {
+                    let operator = match op {
+                        OpCode::Equal => TokenType::EqualEqual,
+                        OpCode::NotEqual => TokenType::BangEqual,
+                        OpCode::Greater => TokenType::Greater,
+                        OpCode::GreaterEqual => TokenType::GreaterEqual,
+                        OpCode::Less => TokenType::Less,
+                        OpCode::LessEqual => TokenType::LessEqual,
+                        OpCode::Add => TokenType::Plus,
+                        OpCode::Subtract => TokenType::Minus,
+                        OpCode::Multiply => TokenType::Star,
+                        _ => TokenType::Slash,
+                    };
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack
+                        .push(interpreter::binary(&operator, left, right, line)?);
+                }
+                OpCode::Not | OpCode::Negate | OpCode::Typeof => {
+                    let operator = match op {
+                        OpCode::Not => TokenType::Bang,
+                        OpCode::Negate => TokenType::Minus,
+                        _ => TokenType::Typeof,
+                    };
+                    let right = self.pop();
+                    self.stack.push(interpreter::unary(&operator, right, line)?);
+                }
+                OpCode::TypeTest => {
+                    let type_name = TYPES[read_byte(chunk, ip) as usize];
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(
+                        type_name == Type::Any || Type::of(&value) == type_name,
+                    ));
+                }
+                OpCode::Print => println!("{}", self.pop()),
+                OpCode::Jump => {
+                    let offset = read_u16(chunk, ip);
+                    *ip += offset as usize;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = read_u16(chunk, ip);
+                    if !interpreter::is_truthy(self.peek()) {
+                        *ip += offset as usize;
+                    }
+                }
+                OpCode::JumpIfNotNil => {
+                    let offset = read_u16(chunk, ip);
+                    if !matches!(self.peek(), Value::Null) {
+                        *ip += offset as usize;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = read_u16(chunk, ip);
+                    *ip -= offset as usize;
+                }
+                OpCode::GetProperty => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let object = self.pop();
+                    self.stack
+                        .push(interpreter::get_property(object, name, line)?);
+                }
+                OpCode::GetMethod => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let receiver = self.peek().clone();
+                    let method = match receiver {
+                        Value::String(_) | Value::List(_) => receiver,
+                        object => interpreter::get_property(object, name, line)?,
+                    };
+                    self.stack.push(method);
+                }
+                OpCode::Invoke => {
+                    let name = chunk.name(read_u16(chunk, ip));
+                    let name_line = read_i32(chunk, ip);
+                    let count = read_byte(chunk, ip) as usize;
+                    let arguments = self.pop_many(count);
+                    let method = self.pop();
+                    let value = match self.pop() {
+                        receiver @ (Value::String(_) | Value::List(_)) => {
+                            methods::call_method(&receiver, name, arguments, name_line)?
+                        }
+                        _ => self.call(method, arguments, line)?,
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Call => {
+                    let count = read_byte(chunk, ip) as usize;
+                    let arguments = self.pop_many(count);
+                    let callee = self.pop();
+                    let value = self.call(callee, arguments, line)?;
+                    self.stack.push(value);
+                }
+                OpCode::Index => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    self.stack.push(methods::index(object, index, line)?);
+                }
+                OpCode::Slice => {
+                    let flags = read_byte(chunk, ip);
+                    let end = (flags & 2 != 0).then(|| self.pop());
+                    let start = (flags & 1 != 0).then(|| self.pop());
+                    let object = self.pop();
+                    self.stack.push(methods::slice(object, start, end, line)?);
+                }
+                OpCode::List => {
+                    let count = read_u16(chunk, ip) as usize;
+                    let values = self.pop_many(count);
+                    self.stack.push(Value::List(Rc::new(RefCell::new(values))));
+                }
+                OpCode::Import => {
+                    let path = chunk.name(read_u16(chunk, ip));
+                    let module = self.import_module(path, line)?;
+                    module
+                        .borrow()
+                        .export_into(&mut self.environment.borrow_mut());
+                }
+                OpCode::ImportAs => {
+                    let path = chunk.name(read_u16(chunk, ip));
+                    let alias = chunk.name(read_u16(chunk, ip));
+                    let module = self.import_module(path, line)?;
+                    self.environment
+                        .borrow_mut()
+                        .define(alias.clone(), Some(Value::Module(alias.clone(), module)));
+                }
+                OpCode::AssertFail => {
+                    let source = chunk.name(read_u16(chunk, ip));
+                    let message = (read_byte(chunk, ip) != 0).then(|| self.pop().to_string());
+                    return Err(RuntimeError::AssertionFailed(
+                        source.to_string(),
+                        message,
+                        line,
+                    ));
+                }
+                OpCode::Throw => return Err(RuntimeError::Thrown(self.pop(), line)),
+                OpCode::PushHandler => {
+                    let offset = read_u16(chunk, ip);
+                    handlers.push(Handler {
+                        target: *ip + offset as usize,
+                        stack_len: self.stack.len(),
+                        locals_len: self.locals.len(),
+                        errors_len: self.errors.len(),
+                        environment: self.environment.clone(),
+                    });
+                }
+                OpCode::PopHandler => {
+                    handlers.pop();
+                }
+                OpCode::Catch => {
+                    let error = self.errors.pop().expect("no caught error");
+                    self.stack.push(error.into_value());
+                }
+                OpCode::Rethrow => return Err(self.errors.pop().expect("no caught error")),
+            }
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        line: i32,
+    ) -> Result<Value, RuntimeError> {
+        match callee {
+            Value::Native(native) => {
+                methods::check_arity(&native.name, native.arity, &arguments, line)?;
+                (native.function)(&mut self.host, &arguments, line)
+            }
+            _ => Err(RuntimeError::NotCallable(line)),
+        }
+    }
+
+    /// Load a module on first import, returning its top-level environment.
+    fn import_module(
+        &mut self,
+        relative: &str,
+        line: i32,
+    ) -> Result<Rc<RefCell<Environment>>, RuntimeError> {
+        let path = interpreter::resolve_import(&self.import_stack, relative, line)?;
+        if let Some(module) = self.modules.get(&path) {
+            return Ok(module.clone());
+        }
+
+        let statements = self.optimize(interpreter::parse_module(&path, relative, line)?);
+        let chunk = compiler::compile(&statements)
+            .map_err(|e| RuntimeError::ImportFailed(relative.to_string(), e.to_string(), line))?;
+        let module = Rc::new(RefCell::new(Environment::new(Some(self.globals.clone()))));
+        let previous = mem::replace(&mut self.environment, module.clone());
+        self.import_stack.push(path.clone());
+        let result = self.run(&chunk);
+        self.import_stack.pop();
+        self.environment = previous;
+        result?;
+
+        self.modules.insert(path, module.clone());
+        Ok(module)
+    }
+}
+
+impl InterpreterLike for Vm {
+    fn new() -> Self {
+        Self::with_host(Host::new())
+    }
+
+    fn optimize(&self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        resolver::resolve(optimizer::optimize(statements))
+    }
+
+    fn set_source_path(&mut self, path: &Path) {
+        self.import_stack.clear();
+        self.import_stack
+            .push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    }
+
+    fn interpret(&mut self, statements: &[Stmt]) {
+        // Like the tree-walk interpreter, an error only abandons its top-level statement.
+        for statement in statements {
+            let result = compiler::compile(std::slice::from_ref(statement))
+                .map_err(|e| e.to_string())
+                .and_then(|chunk| self.run(&chunk).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                eprintln!("{e}");
+            }
+        }
+    }
+}
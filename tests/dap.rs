@@ -0,0 +1,143 @@
+//! A scripted session with `warlox dap`, checking the messages it sends back.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+
+/// Frame requests as the Debug Adapter Protocol does, numbering them from 1.
+fn requests(requests: &[&str]) -> String {
+    let mut input = String::new();
+    for (index, request) in requests.iter().enumerate() {
+        let body = format!("{{\"seq\":{},\"type\":\"request\",{request}}}", index + 1);
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+    }
+    input
+}
+
+/// Split framed output into message bodies.
+fn messages(mut output: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    while let Some((header, rest)) = output.split_once("\r\n\r\n") {
+        let length: usize = header
+            .strip_prefix("Content-Length: ")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| panic!("bad header {header:?}"));
+        messages.push(rest[..length].to_string());
+        output = &rest[length..];
+    }
+    assert_eq!(output, "", "trailing output");
+    messages
+}
+
+#[test]
+fn dap_session_stops_at_breakpoints_and_terminates() {
+    let directory = env::temp_dir().join(format!("warlox-test-{}-dap", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let program = directory.join("main.lox");
+    fs::write(
+        &program,
+        "var total = 0;\nfor (var i = 1; i <= 2; i = i + 1) {\n  var sq = i * i;\n  total = total + sq;\n}\nprint total;\n",
+    )
+    .unwrap();
+    let path = format!("{:?}", program.canonicalize().unwrap().to_str().unwrap());
+    let input = requests(&[
+        r#""command":"initialize","arguments":{"adapterID":"warlox"}"#,
+        &format!(r#""command":"launch","arguments":{{"program":{path}}}"#),
+        &format!(
+            r#""command":"setBreakpoints","arguments":{{"source":{{"path":{path}}},"breakpoints":[{{"line":4}}]}}"#
+        ),
+        r#""command":"configurationDone""#,
+        r#""command":"stackTrace","arguments":{"threadId":1}"#,
+        r#""command":"continue","arguments":{"threadId":1}"#,
+        &format!(
+            r#""command":"setBreakpoints","arguments":{{"source":{{"path":{path}}},"breakpoints":[]}}"#
+        ),
+        r#""command":"continue","arguments":{"threadId":1}"#,
+        r#""command":"disconnect""#,
+    ]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .arg("dap")
+        // Frames are named relative to the working directory.
+        .current_dir(&directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = fs::remove_dir_all(&directory);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let stopped = r#""type":"event","event":"stopped","body":{"reason":"breakpoint","threadId":1,"allThreadsStopped":true}"#;
+    let expected = [
+        r#"{"seq":1,"type":"response","request_seq":1,"success":true,"command":"initialize","body":{"supportsConfigurationDoneRequest":true,"supportsEvaluateForHovers":true}}"#.to_string(),
+        r#"{"seq":2,"type":"event","event":"initialized","body":{}}"#.to_string(),
+        r#"{"seq":3,"type":"response","request_seq":2,"success":true,"command":"launch","body":{}}"#.to_string(),
+        r#"{"seq":4,"type":"response","request_seq":3,"success":true,"command":"setBreakpoints","body":{"breakpoints":[{"verified":true,"line":4}]}}"#.to_string(),
+        r#"{"seq":5,"type":"response","request_seq":4,"success":true,"command":"configurationDone","body":{}}"#.to_string(),
+        format!(r#"{{"seq":6,{stopped}}}"#),
+        format!(
+            r#"{{"seq":7,"type":"response","request_seq":5,"success":true,"command":"stackTrace","body":{{"stackFrames":[{{"id":1,"name":"main.lox","line":4,"column":1,"source":{{"name":"main.lox","path":{path}}}}}],"totalFrames":1}}}}"#
+        ),
+        r#"{"seq":8,"type":"response","request_seq":6,"success":true,"command":"continue","body":{"allThreadsContinued":true}}"#.to_string(),
+        // The breakpoint is on the loop body, reached again on the second iteration.
+        format!(r#"{{"seq":9,{stopped}}}"#),
+        r#"{"seq":10,"type":"response","request_seq":7,"success":true,"command":"setBreakpoints","body":{"breakpoints":[]}}"#.to_string(),
+        r#"{"seq":11,"type":"response","request_seq":8,"success":true,"command":"continue","body":{"allThreadsContinued":true}}"#.to_string(),
+        r#"{"seq":12,"type":"event","event":"output","body":{"category":"stdout","output":"5\n"}}"#.to_string(),
+        r#"{"seq":13,"type":"event","event":"terminated","body":{}}"#.to_string(),
+        r#"{"seq":14,"type":"event","event":"exited","body":{"exitCode":0}}"#.to_string(),
+        r#"{"seq":15,"type":"response","request_seq":9,"success":true,"command":"disconnect","body":{}}"#.to_string(),
+    ];
+    assert_eq!(messages(&String::from_utf8_lossy(&output.stdout)), expected);
+}
+
+#[test]
+fn dap_skips_oversized_and_deeply_nested_messages() {
+    let deep = format!(
+        "{{\"seq\":1,\"type\":\"request\",\"arguments\":{}{}}}",
+        "[".repeat(100_000),
+        "]".repeat(100_000)
+    );
+    let mut input = format!("Content-Length: {}\r\n\r\n{deep}", deep.len());
+    input.push_str(&requests(&[
+        r#""command":"initialize","arguments":{"adapterID":"warlox"}"#,
+    ]));
+    // Far more than the adapter buffers, and more than is ever sent.
+    input.push_str("Content-Length: 99999999999\r\n\r\n{}");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .arg("dap")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Ignoring malformed message: JSON nested deeper than 128 levels at offset 165.\nIgnoring message of 99999999999 bytes, over the limit of 16777216.\n"
+    );
+    let messages = messages(&String::from_utf8_lossy(&output.stdout));
+    assert_eq!(messages.len(), 2, "{messages:?}");
+    assert!(
+        messages[0].contains(r#""command":"initialize""#),
+        "{messages:?}"
+    );
+}
@@ -0,0 +1,794 @@
+//! Lox programs run through the warlox binary, checking what they print.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Write a program to a fresh file in the temporary directory.
+fn script(source: &str) -> PathBuf {
+    let name = format!(
+        "warlox-test-{}-{}.lox",
+        process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = env::temp_dir().join(name);
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Run a program with extra arguments, returning what it printed to stdout and stderr.
+fn run(source: &str, args: &[&str]) -> (String, String) {
+    let (stdout, stderr, _) = run_with_status(source, args);
+    (stdout, stderr)
+}
+
+/// Like `run`, also returning the exit status.
+fn run_with_status(source: &str, args: &[&str]) -> (String, String, Option<i32>) {
+    let path = script(source);
+    let output = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&path);
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+        output.status.code(),
+    )
+}
+
+#[test]
+fn unary_operators_apply_to_their_operand() {
+    let (stdout, stderr) = run(
+        "print -2;\nprint -(1 + 2);\nprint !true;\nprint !nil;\n",
+        &[],
+    );
+    assert_eq!(stdout, "-2\n-3\nfalse\ntrue\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn unterminated_string_is_reported_at_its_start() {
+    let (_, stderr) = run("print 1;\nprint \"abc\ndef", &[]);
+    assert_eq!(
+        stderr.lines().next(),
+        Some("[line 2] Error: Unterminated string.")
+    );
+}
+
+#[test]
+fn try_catch_finally_runs_handlers_in_order() {
+    let source = "try {\n  print \"body\";\n  throw \"boom\";\n  print \"unreached\";\n} catch (e) {\n  print \"caught \" + e;\n} finally {\n  print \"finally\";\n}\ntry {\n  print \"quiet\";\n} finally {\n  print \"finally again\";\n}\n";
+    let (stdout, stderr) = run(source, &[]);
+    assert_eq!(stdout, "body\ncaught boom\nfinally\nquiet\nfinally again\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn caught_errors_have_message_and_line() {
+    let source = "try {\n  var x = 1 / nil;\n} catch (e) {\n  print \"err: \" + e.message;\n  print e.line;\n  print e.nope;\n}\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(source, &[backend]);
+        assert_eq!(stdout, "err: Operands must be numbers.\n2\n");
+        assert!(stderr.contains("Undefined property nope."), "{stderr}");
+    }
+}
+
+#[test]
+fn uncaught_throw_runs_finally_and_reports_the_value() {
+    let source = "try {\n  throw 42;\n} finally {\n  print \"cleanup\";\n}\nprint \"next\";\n";
+    let (stdout, stderr) = run(source, &[]);
+    // Like any runtime error, it ends only the top-level statement it escapes.
+    assert_eq!(stdout, "cleanup\nnext\n");
+    assert!(
+        stderr.contains("Uncaught exception: 42\n[line 2]"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn break_is_not_caught_and_runs_finally() {
+    let source = "while (true) {\n  try {\n    break;\n  } catch (e) {\n    print \"caught\";\n  } finally {\n    print \"finally\";\n  }\n}\nprint \"done\";\n";
+    let (stdout, stderr) = run(source, &[]);
+    assert_eq!(stdout, "finally\ndone\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn break_inside_finally_discards_the_pending_error() {
+    let source = "loop {\n  try {\n    throw \"lost\";\n  } finally {\n    break;\n  }\n}\nprint \"after\";\n";
+    let (stdout, stderr) = run(source, &[]);
+    assert_eq!(stdout, "after\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn failed_assertions_quote_the_condition_as_written() {
+    let cases = [
+        (
+            "var x = 1;\nassert x == -1 and !true;\n",
+            "Assertion failed: x == -1 and !true\n[line 2]",
+        ),
+        (
+            "var x = 1;\nassert (x+1) * 2 ==\n  5, \"doubling\";\n",
+            "Assertion failed: (x+1) * 2 == 5: doubling\n[line 2]",
+        ),
+    ];
+    for (source, expected) in cases {
+        for backend in ["--backend=tree", "--backend=vm"] {
+            let (_, stderr) = run(source, &[backend]);
+            assert!(stderr.starts_with(expected), "{stderr}");
+        }
+    }
+}
+
+#[test]
+fn imported_modules_run_once_and_share_bindings() {
+    let module = script("print \"loading\";\nvar answer = 42;\n");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let source = format!(
+        "import \"{name}\";\nimport \"{name}\" as m;\nprint answer;\nprint m.answer;\nprint m.nope;\n"
+    );
+    let (stdout, stderr) = run(&source, &[]);
+    let _ = fs::remove_file(&module);
+    assert_eq!(stdout, "loading\n42\n42\n");
+    assert!(stderr.contains("Undefined property nope."), "{stderr}");
+}
+
+#[test]
+fn modules_with_syntax_errors_are_not_run() {
+    let module = script("var = 3;\nprint \"module ran\";\n");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let source = format!("import \"{name}\";\nprint \"after\";\n");
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(&source, &[backend]);
+        assert_eq!(stdout, "after\n");
+        assert!(
+            stderr.starts_with(&format!(
+                "Could not import {name}: line 1 at '=' Expect variable name.\n[line 1]"
+            )),
+            "{stderr}"
+        );
+    }
+    let _ = fs::remove_file(&module);
+}
+
+#[test]
+fn missing_modules_fail_to_import() {
+    let (stdout, stderr) = run(
+        "import \"warlox-no-such-module.lox\";\nprint \"after\";\n",
+        &[],
+    );
+    assert_eq!(stdout, "after\n");
+    assert!(
+        stderr.starts_with("Could not import warlox-no-such-module.lox: "),
+        "{stderr}"
+    );
+    assert!(stderr.contains("[line 1]"), "{stderr}");
+}
+
+#[test]
+fn import_cycles_are_reported_with_their_chain() {
+    let first = script("");
+    let second = script("");
+    let first_name = first.file_name().unwrap().to_str().unwrap();
+    let second_name = second.file_name().unwrap().to_str().unwrap();
+    fs::write(&first, format!("import \"{second_name}\";\n")).unwrap();
+    fs::write(&second, format!("import \"{first_name}\";\n")).unwrap();
+    // The chain names files by their canonical paths.
+    let chain = format!(
+        "{} -> {} -> {}",
+        first.canonicalize().unwrap().display(),
+        second.canonicalize().unwrap().display(),
+        first.canonicalize().unwrap().display()
+    );
+    let (_, stderr) = run(&format!("import \"{first_name}\";\n"), &[]);
+    let _ = fs::remove_file(&first);
+    let _ = fs::remove_file(&second);
+    assert!(
+        stderr.starts_with(&format!("Import cycle: {chain}.\n")),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn lists_are_indexed_and_sliced() {
+    let source = "var l = [1, \"two\", nil, [3]];\nprint l[1];\nprint l[3][0];\nprint l[1:3];\nprint l[:0];\nprint l[2:];\nprint l[3:1];\nprint [1, [2]] == [1, [2]];\nvar m = l;\nprint m == l;\n";
+    let (stdout, stderr) = run(source, &[]);
+    assert_eq!(
+        stdout,
+        "two\n3\n[two, null]\n[]\n[null, [3]]\n[]\ntrue\ntrue\n"
+    );
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn bad_indexes_are_runtime_errors() {
+    let cases = [
+        ("print [1, 2][2];\n", "Index 2 out of range for length 2."),
+        ("print [][0];\n", "Index 0 out of range for length 0."),
+        ("print [1][-1];\n", "Index -1 out of range for length 1."),
+        ("print [1][0:2];\n", "Index 2 out of range for length 1."),
+        ("print [1][0.5];\n", "Index must be an integer."),
+        ("print [1][nil];\n", "Index must be an integer."),
+        ("print 12[0];\n", "Only strings and lists can be indexed."),
+    ];
+    for (source, message) in cases {
+        let (stdout, stderr) = run(source, &[]);
+        assert_eq!(stdout, "", "{source}");
+        assert_eq!(stderr, format!("{message}\n[line 1]\n"), "{source}");
+    }
+}
+
+#[test]
+fn strings_are_indexed_sliced_and_compared_by_character() {
+    let source = "var s = \"héllo\";\nprint s[1];\nprint s[1:3];\nprint s[3:1] == \"\";\nprint \"apple\" < \"banana\";\nprint \"b\" >= \"b\";\nprint \"Z\" > \"a\";\n";
+    let (stdout, stderr) = run(source, &[]);
+    assert_eq!(stdout, "é\nél\ntrue\ntrue\ntrue\nfalse\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn string_and_list_methods() {
+    let source = "print \"héllo\".len();\nprint \"MiXed\".upper() + \" \" + \"MiXed\".lower();\nprint \"[\" + \"  pad \".trim() + \"]\";\nprint \"a,b,,c\".split(\",\");\nprint \"abc\".split(\"\");\nprint \"haystack\".contains(\"st\");\nprint \"haystack\".starts_with(\"st\");\nprint \"a-b-c\".replace(\"-\", \"+\");\nprint \"héllo\".find(\"llo\");\nprint \"abc\".find(\"x\");\nprint \"ab\".repeat(3);\nprint \"ab\".repeat(0) == \"\";\nprint [1, 2, 3].len();\nprint \"a,b\".split(\",\").len();\n";
+    let (stdout, stderr) = run(source, &[]);
+    assert_eq!(
+        stdout,
+        "5\nMIXED mixed\n[pad]\n[a, b, , c]\n[a, b, c]\ntrue\nfalse\na+b+c\n2\nnull\nababab\ntrue\n3\n2\n"
+    );
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn bad_method_calls_are_runtime_errors() {
+    let cases = [
+        ("print \"a\".nope();\n", "Undefined method nope for string."),
+        ("print [].upper();\n", "Undefined method upper for list."),
+        (
+            "print \"a\".len(1);\n",
+            "len expects 0 arguments but got 1.",
+        ),
+        (
+            "print \"a\".split(1);\n",
+            "Argument 1 of split must be a string.",
+        ),
+        (
+            "print \"a\".repeat(-1);\n",
+            "Argument 1 of repeat is out of range.",
+        ),
+        (
+            "print \"a\".repeat(1.5);\n",
+            "Argument 1 of repeat is out of range.",
+        ),
+        ("print 1();\n", "Can only call functions and methods."),
+    ];
+    for (source, message) in cases {
+        let (stdout, stderr) = run(source, &[]);
+        assert_eq!(stdout, "", "{source}");
+        assert_eq!(stderr, format!("{message}\n[line 1]\n"), "{source}");
+    }
+}
+
+#[test]
+fn repeat_refuses_strings_too_long_to_build() {
+    for backend in ["--backend=tree", "--backend=vm"] {
+        for count in ["100000000000000000000", "1000000000000"] {
+            let (stdout, stderr) = run(&format!("print \"ab\".repeat({count});\n"), &[backend]);
+            assert_eq!(stdout, "");
+            assert!(stderr.contains("Argument 1 of repeat"), "{stderr}");
+        }
+    }
+}
+
+#[test]
+fn random_int_refuses_ranges_too_wide_to_draw_from() {
+    for hi in ["pow(10, 20)", "pow(2, 64) - 1", "pow(2, 53)"] {
+        let (stdout, stderr) = run(&format!("print random_int(0, {hi});\n"), &[]);
+        assert_eq!(stdout, "");
+        assert!(stderr.contains("Argument 2 of random_int"), "{stderr}");
+    }
+    let (stdout, stderr) = run("print random_int(5, 5);\n", &["--seed", "1"]);
+    assert_eq!(stdout, "5\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn seeded_runs_are_reproducible() {
+    let source = "print random();\nprint random_int(1, 1000000);\nvar l = [1, 2, 3, 4, 5, 6, 7, 8];\nshuffle(l);\nprint l;\nprint choice(l);\n";
+    let (first, stderr) = run(source, &["--seed", "39"]);
+    assert_eq!(stderr, "");
+    let (second, _) = run(source, &["--seed", "39"]);
+    assert_eq!(first, second);
+    let (other, _) = run(source, &["--seed", "40"]);
+    assert_ne!(first, other);
+}
+
+#[test]
+fn random_int_covers_both_bounds_and_nothing_else() {
+    let source = "var low = 0;\nvar high = 0;\nfor (var i = 0; i < 400; i = i + 1) {\n  var n = random_int(-2, 1);\n  assert n >= -2 and n <= 1 and floor(n) == n;\n  if (n == -2) low = low + 1;\n  if (n == 1) high = high + 1;\n}\nprint low > 0 and high > 0;\nprint random_int(7, 7);\nprint random_int(0, pow(2, 53) - 1) >= 0;\n";
+    let (stdout, stderr) = run(source, &["--seed", "1"]);
+    assert_eq!(stdout, "true\n7\ntrue\n");
+    assert_eq!(stderr, "");
+    let cases = [
+        (
+            "print random_int(2, 1);\n",
+            "Argument 2 of random_int is out of range.",
+        ),
+        (
+            "print random_int(0.5, 1);\n",
+            "Argument 1 of random_int is out of range.",
+        ),
+        (
+            "print random_int(0, INF);\n",
+            "Argument 2 of random_int is out of range.",
+        ),
+        (
+            "print random_int(NAN, 1);\n",
+            "Argument 1 of random_int is out of range.",
+        ),
+    ];
+    for (source, message) in cases {
+        let (stdout, stderr) = run(source, &[]);
+        assert_eq!(stdout, "", "{source}");
+        assert_eq!(stderr, format!("{message}\n[line 1]\n"), "{source}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn allow_fs_refuses_links_to_missing_files_outside_the_root() {
+    let base = env::temp_dir().join(format!("warlox-test-{}-links", process::id()));
+    let root = base.join("root");
+    let outside = base.join("outside");
+    fs::create_dir_all(&root).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(outside.join("pwned.txt"), root.join("link")).unwrap();
+    std::os::unix::fs::symlink("link", root.join("chain")).unwrap();
+    let allow = format!("--allow-fs={}", root.display());
+    let (_, stderr) = run("write_file(\"link\", \"escaped\");\n", &[&allow]);
+    let (_, chained) = run("append_file(\"chain\", \"escaped\");\n", &[&allow]);
+    let escaped = outside.join("pwned.txt").exists();
+    let _ = fs::remove_dir_all(&base);
+    assert!(stderr.contains("outside"), "{stderr}");
+    assert!(chained.contains("outside"), "{chained}");
+    assert!(!escaped);
+}
+
+#[test]
+fn allow_fs_confines_paths_to_the_root() {
+    let base = env::temp_dir().join(format!("warlox-test-{}-confine", process::id()));
+    let root = base.join("root");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(base.join("secret.txt"), "secret").unwrap();
+    let allow = format!("--allow-fs={}", root.display());
+    let inside = root.join("inner.txt");
+    let source = format!(
+        "write_file(\"inner.txt\", \"ok\");\nprint read_file(\"sub/../inner.txt\");\nprint read_file({:?});\nprint file_exists(\"missing.txt\");\n",
+        inside.display().to_string()
+    );
+    let (stdout, stderr) = run(&source, &[&allow]);
+    assert_eq!(stdout, "ok\nok\nfalse\n");
+    assert_eq!(stderr, "");
+
+    let secret = base.join("secret.txt").display().to_string();
+    for path in ["../secret.txt", "sub/../../secret.txt", secret.as_str()] {
+        let (stdout, stderr) = run(&format!("print read_file({path:?});\n"), &[&allow]);
+        assert_eq!(stdout, "", "{path}");
+        assert_eq!(
+            stderr,
+            format!("Path {path} is outside the allowed directory.\n[line 1]\n")
+        );
+    }
+    let (_, stderr) = run("write_file(\"../escape.txt\", \"x\");\n", &[&allow]);
+    assert!(stderr.contains("outside the allowed directory"), "{stderr}");
+    let escaped = base.join("escape.txt").exists();
+
+    let (_, denied) = run("print read_file(\"inner.txt\");\n", &[]);
+    let _ = fs::remove_dir_all(&base);
+    assert!(!escaped);
+    assert_eq!(
+        denied,
+        "Filesystem access is disabled; run with --allow-fs.\n[line 1]\n"
+    );
+}
+
+#[test]
+fn block_locals_behave_alike_on_both_backends() {
+    let source = "{\n  var a = 1;\n  { var b = a + 1; { var c = b * 2; a = c; } }\n  var a = a + 10;\n  print a;\n  var u;\n  print u ?? 0;\n}\n\
+{\n  var t = \"outer\";\n  loop {\n    var q = 1;\n    try { var z = q; break; } finally { var f = t; print f; }\n  }\n  var after = t + \"!\";\n  print after;\n}\n\
+{\n  var n = 3;\n  try { var inner = 1; throw n; } catch (e) { var m = e + n; print m; }\n  match (n) {\n    case 3: var r = n * 2; print r;\n    default: print 0;\n  }\n  print n;\n}\n";
+    let (tree, tree_errors) = run(source, &["--backend=tree"]);
+    let (vm, vm_errors) = run(source, &["--backend=vm"]);
+    assert_eq!(tree, "14\nouter\nouter!\n6\n6\n3\n");
+    assert_eq!(vm, tree);
+    assert!(tree_errors.starts_with("Variable u has not been initialized."));
+    assert_eq!(vm_errors, tree_errors);
+}
+
+#[test]
+fn both_backends_print_and_report_the_same() {
+    let module = script("var answer = 42;\nvar label = \"m\";\n");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let programs = [
+        "print 1 + 2 * 3 - 4 / 2;\nprint -(1);\nprint !nil;\nprint \"a\" + 1;\nprint 1 < 2 and 2 >= 3 or \"x\";\nprint 1 == 1.0;\nprint nil != false;\n".to_string(),
+        "var x = 1, 2;\nprint x;\nprint x > 1 ? \"big\" : \"small\";\nvar y;\ny = x = 5;\nprint y;\nprint y ?? 0;\nconst c = 3;\nprint c;\n".to_string(),
+        "var i = 0;\nwhile (i < 3) { print i; i = i + 1; }\ndo { i = i - 1; if (i == 1) break; } while (i > 0);\nprint i;\nfor (var j = 0; j < 2; j = j + 1) print j;\nloop { break; }\n".to_string(),
+        "match (2) {\n  case 1, 2: print \"low\";\n  case 3: print \"three\";\n  default: print \"other\";\n}\nmatch (\"z\") { case \"a\": print 1; }\n".to_string(),
+        "print typeof 1;\nprint typeof \"s\";\nprint typeof [1];\nprint typeof nil;\nprint 1 is number;\nprint \"s\" is list;\n".to_string(),
+        "var l = [1, [2, 3], \"four\"];\nprint l;\nprint l[1][0];\nprint l[-1];\nprint l[1:];\nprint l[:1];\nprint \"héllo\"[1:3];\nprint \"abc\".upper().len();\nprint [3, 1].len();\n".to_string(),
+        "print sqrt(16);\nprint floor(2.5) + abs(-1);\nprint max(1, 2);\nprint pow(2, 10) + PI;\n".to_string(),
+        "try { throw [1]; } catch (e) { print e; } finally { print \"f\"; }\ntry { print nope; } catch (e) { print e.message; print e.line; }\n".to_string(),
+        "assert 1 < 2;\nassert 2 < 1, \"order\";\nprint \"after\";\n".to_string(),
+        // Runtime errors end their top-level statement only.
+        "print 1 / 0;\nprint \"a\" - 1;\nprint nope;\nnope = 1;\nprint [1][5];\nprint 1();\nprint 1.nope;\nprint \"a\".nope();\nprint \"a\".len(1);\nthrow \"up\";\nprint \"end\";\n".to_string(),
+        // A failed property lookup comes before the arguments are evaluated.
+        format!("import \"{name}\" as m;\nvar seen = 0;\nprint m.answer;\nprint m.label.upper();\nprint m.missing(seen = 1);\nprint seen;\nprint 1.len(seen = 2);\nprint seen;\nprint m.answer(seen = 3);\nprint seen;\nprint m.label();\n"),
+        format!("{{\n  import \"{name}\";\n  var local = answer + 1;\n  print local;\n}}\nprint label;\n"),
+    ];
+    for source in &programs {
+        let tree = run(source, &["--backend=tree"]);
+        let vm = run(source, &["--backend=vm"]);
+        assert_eq!(vm, tree, "{source}");
+    }
+    let _ = fs::remove_file(&module);
+}
+
+#[test]
+fn disassembly_lists_each_top_level_statement() {
+    let source =
+        "var n = 2;\nwhile (n > 0) {\n  var m = n;\n  n = m - 1;\n}\nprint \"ab\".len();\n";
+    let (stdout, stderr) = run(source, &["--disassemble"]);
+    let expected = "\
+== statement 1 ==
+0000    1 OP_CONSTANT         0 '2'
+0003    | OP_DEFINE_VAR       1 'n'
+== statement 2 ==
+0000    2 OP_GET_VAR          0 'n'
+0003    | OP_CONSTANT         1 '0'
+0006    | OP_GREATER
+0007    | OP_JUMP_IF_FALSE    7 -> 38
+0010    | OP_POP
+0011    3 OP_GET_VAR          0 'n'
+0014    | OP_DEFINE_LOCAL     2 'm' @0
+0019    4 OP_GET_LOCAL        2 'm' @0
+0024    | OP_CONSTANT         3 '1'
+0027    | OP_SUBTRACT
+0028    | OP_SET_VAR          0 'n'
+0031    | OP_POP
+0032    | OP_POP_LOCALS    @0
+0035    | OP_LOOP            35 -> 0
+0038    | OP_POP
+== statement 3 ==
+0000    6 OP_CONSTANT         0 'ab'
+0003    | OP_GET_METHOD       1 'len'
+0006    | OP_INVOKE        (0 args)    1 'len' line 6
+0014    | OP_PRINT
+";
+    assert_eq!(stdout, expected);
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn folded_operations_that_would_fail_still_fail_at_runtime_on_their_line() {
+    let source = "print 1 +\n  2 * 3;\nprint 1 /\n  0;\nprint \"a\" -\n  1;\nprint \"after\";\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(source, &[backend]);
+        assert_eq!(stdout, "7\nafter\n");
+        assert_eq!(
+            stderr,
+            "Division by zero\n[line 3]\nOperands must be numbers.\n[line 5]\n"
+        );
+    }
+}
+
+#[test]
+fn dead_branches_are_dropped_without_losing_side_effects() {
+    let source = "var x = 0;\nif (false) x = 1; else x = 2;\nprint x;\nwhile (false) x = 3;\nif ((x = 4) == 5) print \"no\";\nprint x;\nwhile ((x = x - 1) > 2) print x;\nprint x;\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(source, &[backend]);
+        assert_eq!(stdout, "2\n4\n3\n2\n");
+        assert_eq!(stderr, "");
+    }
+    let (stdout, _) = run(
+        "while (false) print 1;\nif (false) print 2;\nprint 60 * 60 * 24;\n",
+        &["--disassemble"],
+    );
+    assert_eq!(
+        stdout,
+        "== statement 1 ==\n0000    3 OP_CONSTANT         0 '86400'\n0003    | OP_PRINT\n"
+    );
+}
+
+#[test]
+fn profile_counts_lines_and_loop_tests() {
+    let path = script("var i = 0;\nwhile (i < 3) {\n  i = i + 1;\n}\ndo {\n  i = i - 1;\n} while (i > 0);\nprint sqrt(16);\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .arg("--profile")
+        .arg(&path)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "4\n");
+
+    let report = String::from_utf8_lossy(&output.stderr);
+    let mut lines = report.lines();
+    let header: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+    assert_eq!(header, ["line", "count", "total", "own"]);
+    let mut counts: Vec<(String, u64)> = lines
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields.len(), 4, "{line}");
+            (fields[0].to_string(), fields[1].parse().unwrap())
+        })
+        .collect();
+    counts.sort();
+    let site = |line: i32| format!("{}:{line}", path.display());
+    let mut expected = vec![
+        (site(1), 1),
+        // Tested four times, entering the body three.
+        (site(2), 4),
+        (site(3), 3),
+        (site(6), 3),
+        // Tested after each of the three runs of the body.
+        (site(7), 3),
+        (site(8), 1),
+        ("sqrt()".to_string(), 1),
+    ];
+    expected.sort();
+    assert_eq!(counts, expected);
+}
+
+#[test]
+fn profile_writes_collapsed_stacks() {
+    let path = script(
+        "var total = 0;\nfor (var i = 0; i < 2000; i = i + 1) {\n  total = total + sqrt(i);\n}\n",
+    );
+    let stacks = env::temp_dir().join(format!("warlox-test-{}-stacks.txt", process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .arg(format!("--profile={}", stacks.display()))
+        .arg(&path)
+        .output()
+        .unwrap();
+    let collapsed = fs::read_to_string(&stacks).unwrap();
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(&stacks);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let site = |line: i32| format!("{}:{line}", path.display());
+    let mut found = Vec::new();
+    for line in collapsed.lines() {
+        let (stack, micros) = line.rsplit_once(' ').unwrap();
+        assert!(micros.parse::<u64>().unwrap() > 0, "{line}");
+        found.push(stack.to_string());
+    }
+    // The loop's body and increment run inside the loop, and sqrt inside the body.
+    let increment = format!("{};{}", site(2), site(2));
+    let body = format!("{};{}", site(2), site(3));
+    assert!(found.contains(&site(2)), "{collapsed}");
+    assert!(found.contains(&body), "{collapsed}");
+    let known = [
+        site(1),
+        site(2),
+        increment,
+        body.clone(),
+        format!("{body};sqrt()"),
+    ];
+    assert!(
+        found.iter().all(|stack| known.contains(stack)),
+        "{collapsed}"
+    );
+}
+
+#[test]
+fn coverage_counts_lines_of_dead_branches() {
+    let output = env::temp_dir().join(format!("warlox-test-{}-dead.info", process::id()));
+    let (stdout, stderr) = run(
+        "var x = 1;\nif (false) {\n  print \"never\";\n}\nwhile (false) {\n  x = 2;\n}\nprint x;\n",
+        &["--coverage", output.to_str().unwrap()],
+    );
+    let lcov = fs::read_to_string(&output).unwrap();
+    let _ = fs::remove_file(&output);
+    assert!(stdout.starts_with("1\n"), "{stdout}");
+    assert!(stderr.contains("66.7% of    6 lines"), "{stderr}");
+    for line in ["DA:3,0", "DA:6,0", "LF:6", "LH:4"] {
+        assert!(
+            lcov.lines().any(|v| v == line),
+            "{line} missing from {lcov}"
+        );
+    }
+}
+
+#[test]
+fn coverage_counts_each_branch_of_a_line_and_of_imported_modules() {
+    let module = script("var m = 0;\nwhile (m < 2) m = m + 1;\n");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let output = env::temp_dir().join(format!("warlox-test-{}-branches.info", process::id()));
+    let source = format!(
+        "import \"{name}\";\nif (m == 2) print \"a\"; if (m == 3) print \"b\";\nmatch (m) {{ case 1: print 1; case 2: print 2; }}\n"
+    );
+    let (stdout, _) = run(&source, &["--coverage", output.to_str().unwrap()]);
+    let lcov = fs::read_to_string(&output).unwrap();
+    let _ = fs::remove_file(&module);
+    let _ = fs::remove_file(&output);
+    assert_eq!(stdout, "a\n2\n");
+
+    let branches: Vec<&str> = lcov.lines().filter(|v| v.starts_with("BRDA:")).collect();
+    assert_eq!(
+        branches,
+        [
+            // Both ifs on line 2, told apart by their position on it.
+            "BRDA:2,0,0,1",
+            "BRDA:2,0,1,0",
+            "BRDA:2,1,0,0",
+            "BRDA:2,1,1,1",
+            // The second case of the match.
+            "BRDA:3,0,0,0",
+            "BRDA:3,0,1,1",
+            "BRDA:3,0,2,0",
+            // The module's loop, entered twice.
+            "BRDA:2,0,0,2",
+            "BRDA:2,0,1,1",
+        ]
+    );
+}
+
+#[test]
+fn debugger_assigns_block_locals_but_not_constants() {
+    let path = script("{\n  const k = 1;\n  var v = 2;\n  print k + v;\n}\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_warlox"))
+        .arg("--debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"break 4\ncontinue\nprint k = 99\nprint v = 5\ncontinue\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = fs::remove_file(&path);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cannot assign to constant k."), "{stderr}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n");
+}
+
+#[test]
+fn do_while_and_loop_run_until_break_or_condition() {
+    let source = "var i = 0;\ndo {\n  i = i + 1;\n} while (i < 0);\nprint i;\ndo {\n  i = i + 1;\n  if (i == 3) break;\n} while (true);\nprint i;\nloop {\n  i = i + 1;\n  if (i > 4) break;\n  print i;\n}\nprint \"done\";\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(source, &[backend]);
+        assert_eq!(stdout, "1\n3\n4\ndone\n");
+        assert_eq!(stderr, "");
+    }
+}
+
+#[test]
+fn break_outside_a_loop_is_a_syntax_error() {
+    let (_, stderr) = run("break;\nloop { break; }\n{\n  break;\n}\n", &[]);
+    assert_eq!(
+        stderr,
+        "1 at 'break' Must be inside a loop to use 'break'.\n4 at 'break' Must be inside a loop to use 'break'.\n"
+    );
+    // Parsing picks up again at a break after an error, so it is still checked.
+    let (_, stderr) = run("var = 1\nbreak;\nprint \"after\";\n", &[]);
+    assert_eq!(
+        stderr,
+        "1 at '=' Expect variable name.\n2 at 'break' Must be inside a loop to use 'break'.\n"
+    );
+}
+
+#[test]
+fn duplicate_default_is_reported_at_its_keyword() {
+    let (_, stderr) = run(
+        "match (1) {\n  default:\n    print 1;\n  default:\n    print 2 +;\n  case 1:\n    print 3;\n}\n",
+        &[],
+    );
+    // In source order, and the arms after it are still parsed.
+    assert_eq!(
+        stderr,
+        "4 at 'default' Match can only have one default arm.\n5 at ';' Expect expression.\n"
+    );
+}
+
+#[test]
+fn match_evaluates_its_subject_once_and_arms_can_break() {
+    let source = "var calls = 0;\nvar i = 0;\nloop {\n  i = i + 1;\n  match (calls = calls + 1) {\n    case 1, 2: print \"low\";\n    case 3:\n      print \"three\";\n      break;\n      print \"unreached\";\n    default: print \"other\";\n  }\n}\nprint calls;\nprint i;\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(source, &[backend]);
+        assert_eq!(stdout, "low\nlow\nthree\n3\n3\n");
+        assert_eq!(stderr, "");
+    }
+}
+
+#[test]
+fn constants_are_refused_reassignment() {
+    // Assignments the parser can see are syntax errors.
+    let (_, stderr) = run(
+        "const a = 1;\na = 2;\n{\n  const b = 2;\n  b = 3;\n}\nconst c;\n",
+        &[],
+    );
+    assert_eq!(
+        stderr,
+        "2 at 'a' Cannot assign to constant.\n5 at 'b' Cannot assign to constant.\n7 at ';' Constant must be initialized.\n"
+    );
+
+    // A constant imported from a module is only known to be one at runtime.
+    let module = script("const a = 1;\n");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let source = format!("import \"{name}\";\na = 5;\n");
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (_, stderr) = run(&source, &[backend]);
+        assert_eq!(stderr, "Cannot assign to constant a.\n[line 2]\n");
+    }
+    let _ = fs::remove_file(&module);
+}
+
+#[test]
+fn check_reports_mismatches_without_running() {
+    let (stdout, stderr, status) = run_with_status(
+        "var x: number = \"a\";\nvar n: number = 1;\nn = \"s\";\nprint n - \"s\";\nvar u = \"s\";\nprint u - 1;\n{\n  var inner: string = \"ok\";\n  print -inner;\n}\n",
+        &["--check"],
+    );
+    assert_eq!(stdout, "");
+    // Unannotated variables are not reported, so line 6 is not.
+    assert_eq!(
+        stderr,
+        "1 at 'x' Cannot initialize variable of type number with string.\n3 at 'n' Cannot assign string to variable of type number.\n4 at '-' Operands must be numbers, found number and string.\n9 at '-' Operand must be a number, found string.\n"
+    );
+    assert_eq!(status, Some(1));
+
+    let (stdout, stderr, status) = run_with_status(
+        "var n: number = 1;\nvar s: string = \"a\" + \"b\";\nprint n + 1;\n",
+        &["--check"],
+    );
+    assert_eq!((stdout.as_str(), stderr.as_str()), ("", ""));
+    assert_eq!(status, Some(0));
+}
+
+#[test]
+fn typeof_and_is_report_runtime_types() {
+    let module = script("var x = 1;\n");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let source = format!(
+        "import \"{name}\" as m;\nprint typeof 1;\nprint typeof \"s\";\nprint typeof true;\nprint typeof nil;\nprint typeof [1];\nprint typeof sqrt;\nprint typeof m;\ntry {{ print 1 / 0; }} catch (e) {{ print typeof e; print e is error; }}\nprint 1 is number;\nprint \"a\" is number;\nprint nil is nil;\nprint [] is list;\nprint typeof typeof 1;\nprint typeof 1 is string;\n"
+    );
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(&source, &[backend]);
+        assert_eq!(
+            stdout,
+            "number\nstring\nboolean\nnil\nlist\nfunction\nmodule\nerror\ntrue\ntrue\nfalse\ntrue\ntrue\nstring\ntrue\n"
+        );
+        assert_eq!(stderr, "");
+    }
+    let _ = fs::remove_file(&module);
+
+    let (_, stderr) = run("print 1 is bogus;\n", &[]);
+    assert_eq!(stderr, "1 at 'bogus' Unknown type.\n");
+}
+
+#[test]
+fn nil_coalescing_short_circuits() {
+    let source = "var calls = 0;\nprint 1 ?? (calls = calls + 1);\nprint false ?? 2;\nprint nil ?? nil ?? 3;\nvar a;\na ??= 4;\nprint a;\na ??= (calls = calls + 1);\nprint a;\nvar n = nil;\nn ??= 5;\nprint n;\n{\n  var b;\n  b ??= 6;\n  var c = 7;\n  c ??= (calls = calls + 1);\n  print b + c;\n}\nprint calls;\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let (stdout, stderr) = run(source, &[backend]);
+        assert_eq!(stdout, "1\nfalse\n3\n4\n4\n5\n13\n0\n");
+        assert_eq!(stderr, "");
+
+        // Only ??= treats an uninitialized variable as nil.
+        let (_, stderr) = run("var a;\nprint a ?? 1;\nb ??= 1;\n", &[backend]);
+        assert_eq!(
+            stderr,
+            "Variable a has not been initialized.\n[line 2]\nUndefined variable b.\n[line 3]\n"
+        );
+    }
+}